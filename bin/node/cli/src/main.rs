@@ -1,34 +1,57 @@
 #[macro_use]
 extern crate log;
+mod status;
+
 use bigdecimal::BigDecimal;
 use blake2::{Blake2s256, Digest};
 use clap_serde_derive::{
-    clap::{self, Parser},
+    clap::{self, Parser, Subcommand},
     ClapSerde,
 };
 use eureka_sink_postgres::{
     cursor::CursorLoader,
     db_loader::DBLoader,
-    flush::FlushLoader,
+    error::DBError,
+    flush::{FlushLoader, FlushPolicy, FlushStrategy},
+    history::HistoryLoader,
+    metadata::MetadataLoader,
+    migrations::{Migration, MigrationLoader},
     ops::DBLoaderOperations,
-    sql_types::{BigInt, Binary, Bool, ColumnValue, Decimal, Integer, Sql, Text},
+    sql_types::{Binary, Bool, ColumnType, ColumnValue, Null, Sql, Text},
+    tables::{TableDefinition, TableLoader},
 };
 use hex::encode;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use offchain::{
     resolver, wasm, ArweaveLinkResolver, HTTPSLinkResolver, IpfsLinkResolver, LinkResolver,
     ResolveTask, Resolver,
 };
-use sqlx::PgPool;
-use std::{collections::HashMap, fs::File, io::Read, str::FromStr, sync::Arc};
+use serde::Deserialize;
+use sqlx::postgres::PgPoolOptions;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::Read,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
 use substreams_sink::pb;
 use substreams_sink::{
-    pb::Value, substreams::pb::response::Message, BlockRef, Cursor, SubstreamsSink,
+    package, pb::Value, BackoffPolicy, BlockRef, Cursor, ModuleInput, ModuleKind, PackageInfo,
+    ProgressStatus, ProgressTracker, ProtocolVersion, ResilientStream, StreamError, StreamMessage,
+    SubstreamsSink, RECORD_CHANGES_TYPE,
 };
-use tokio_stream::StreamExt;
+use tokio::sync::mpsc::Sender;
 
 const DOMAIN_SEPARATION_LABEL: &str = "bin.node.cli.PRIMARY_KEY_INSERT_INTO";
+/// Metadata key recording the scheme from which single column primary keys are derived.
+const PRIMARY_KEY_SCHEME_KEY: &str = "primary_key_scheme";
+/// Primary keys are derived from the record id alone, see [`get_primary_key`]. Earlier
+/// versions derived them from the record id and ordinal, and recorded no scheme.
+const PRIMARY_KEY_SCHEME: &str = "blake2s-id";
 
 #[derive(Parser)]
 #[clap(author, version, about)]
@@ -40,6 +63,30 @@ struct Args {
     /// Rest of arguments
     #[clap(flatten)]
     pub config: <Config as ClapSerde>::Opt,
+
+    /// Command to run, streams the package if none is given
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Applies the pending schema migrations, then exits
+    Migrate,
+    /// Rewinds the DB to the last cursor written at or before a block, reverting the
+    /// changes recorded after it, so that streaming resumes from there, then exits. Changes
+    /// not recorded, out of live mode, are rewound by deleting the rows written after the
+    /// block, which requires block columns
+    Rewind {
+        /// Block to rewind to
+        block: u64,
+    },
+    /// Prints the modules of a package, along with their inputs, in dependency order,
+    /// then exits
+    Info {
+        /// Package file name (*.spkg)
+        spkg: String,
+    },
 }
 
 #[derive(ClapSerde, Debug)]
@@ -47,6 +94,9 @@ struct Config {
     /// Firehose endpoint
     #[clap(short, long)]
     firehose_endpoint: String,
+    /// Substreams RPC protocol version of the endpoint, `v1` or `v2`
+    #[clap(long, default_value = "v2")]
+    substreams_protocol: String,
     /// Package file name (*.pkg)
     #[clap(short, long)]
     package_file_name: String,
@@ -62,12 +112,30 @@ struct Config {
     /// Postgres database source name to establish DB connection
     #[clap(long)]
     postgres_dsn: String,
+    /// Maximum number of connections of each Postgres connection pool
+    #[clap(long, default_value = "10")]
+    postgres_max_connections: u32,
     /// DB schema name
     #[clap(long)]
     schema: String,
-    /// SQL Schema file name (*.sql)
+    /// SQL Schema file name (*.sql), executed as a whole on every run
     #[clap(long)]
     schema_file_name: String,
+    /// Directory of the schema migrations (<version>_<name>.sql), applied in order
+    /// instead of the schema file
+    #[clap(long)]
+    migrations_dir: String,
+    /// Record schema file name (*.toml), declaring the tables to create or alter
+    #[clap(long)]
+    record_schema_file: String,
+    /// Create and alter tables from the records of the stream, with column types
+    /// inferred from the field values
+    #[clap(long, default_value = "false")]
+    auto_create_tables: bool,
+    /// Add `_block_num`, `_block_id` and `_block_timestamp` columns to every table,
+    /// filled in with the block of each insert and update
+    #[clap(long, default_value = "false")]
+    block_columns: bool,
     /// IPFS clients
     #[clap(short, long, value_parser, num_args = 0.., value_delimiter = ' ')]
     ipfs_clients: Vec<String>,
@@ -77,6 +145,49 @@ struct Config {
     /// Maximum number of cuncurrent resolver tasks
     #[clap(long, default_value = "10")]
     max_concurrent_resolver_tasks: usize,
+    /// Flush strategy, one of `single`, `batch` or `copy`, the latter connecting without TLS
+    #[clap(long, default_value = "batch")]
+    flush_strategy: String,
+    /// Minimum number of pending rows to flush with `COPY`, for the `copy` flush strategy
+    #[clap(long, default_value = "10000")]
+    copy_min_rows: u64,
+    /// Flush once this number of operations is pending, 0 to disable
+    #[clap(long, default_value = "0")]
+    flush_max_entries: u64,
+    /// Flush every this number of blocks, 0 to disable
+    #[clap(long, default_value = "1")]
+    flush_every_blocks: u64,
+    /// Flush every this number of seconds, 0 to disable
+    #[clap(long, default_value = "0")]
+    flush_interval_secs: u64,
+    /// Flush every block, once blocks are at most this number of seconds old, 0 to disable
+    #[clap(long, default_value = "0")]
+    flush_live_block_age_secs: u64,
+    /// Follow the chain head, streaming reversible blocks and undoing forked ones,
+    /// instead of irreversible blocks only. Every block is then flushed on its own
+    #[clap(long, default_value = "false")]
+    live: bool,
+    /// Number of blocks for which changes are kept, to be undone or rewound, in live mode
+    #[clap(long, default_value = "1000")]
+    history_depth: u64,
+    /// Number of blocks for which cursors are kept, to be rewound to, 0 to only keep the
+    /// latest one, unless in live mode
+    #[clap(long, default_value = "1000")]
+    rewind_depth: u64,
+    /// Resume streaming after the block of a cursor written for another hash of the
+    /// output module, e.g. once the package is rebuilt, instead of refusing to
+    #[clap(long, default_value = "false")]
+    allow_module_hash_change: bool,
+    /// Maximum number of consecutive reconnections of the stream, 0 to disable them
+    #[clap(long, default_value = "10")]
+    stream_max_retries: u32,
+    /// Log the progress of the stream every this number of seconds, 0 to disable
+    #[clap(long, default_value = "30")]
+    progress_log_interval_secs: u64,
+    /// Address on which the progress of the stream is served, on `GET /status`,
+    /// e.g. `127.0.0.1:9102`. Disabled if empty
+    #[clap(long)]
+    status_address: String,
 }
 
 #[tokio::main]
@@ -95,48 +206,248 @@ async fn main() {
     };
 
     // Check required parameters until the macro is supported in clap-serde-derive merge
-    if config.firehose_endpoint.len() == 0
-        || config.package_file_name.len() == 0
-        || config.module_name.len() == 0
-        || config.schema_file_name.len() == 0
-        || config.schema.len() == 0
-        || config.postgres_dsn.len() == 0
-        || (config.start_block == 0 && config.end_block == 0)
-    {
+    let missing_arguments = match args.command {
+        Some(Command::Migrate) => {
+            config.migrations_dir.len() == 0
+                || config.schema.len() == 0
+                || config.postgres_dsn.len() == 0
+        }
+        Some(Command::Rewind { .. }) => {
+            config.package_file_name.len() == 0
+                || config.module_name.len() == 0
+                || config.schema.len() == 0
+                || config.postgres_dsn.len() == 0
+        }
+        Some(Command::Info { .. }) => false,
+        None => {
+            config.firehose_endpoint.len() == 0
+                || config.package_file_name.len() == 0
+                || config.module_name.len() == 0
+                || (config.schema_file_name.len() == 0
+                    && config.migrations_dir.len() == 0
+                    && config.record_schema_file.len() == 0
+                    && !config.auto_create_tables)
+                || config.schema.len() == 0
+                || config.postgres_dsn.len() == 0
+                || (config.start_block == 0 && config.end_block == 0)
+        }
+    };
+    if missing_arguments {
         error!("Missing or invalid arguments. Use -h for help.");
         return;
     }
 
-    if let Err(e) = run(config).await {
+    let result = match args.command {
+        Some(Command::Migrate) => migrate(config).await,
+        Some(Command::Rewind { block }) => rewind(config, block).await,
+        Some(Command::Info { spkg }) => info(config, &spkg),
+        None => run(config).await,
+    };
+    if let Err(e) = result {
         error!("Error: {}", e);
     }
 }
 
+/// Applies the pending schema migrations of the migrations directory.
+async fn migrate(config: Config) -> Result<()> {
+    let db_loader = DBLoader::new(
+        config.postgres_dsn.clone(),
+        config.schema.clone(),
+        config.postgres_max_connections as usize,
+    )
+    .await?;
+    apply_migrations(&db_loader, &config.migrations_dir).await
+}
+
+/// Rewinds the DB to the last cursor written at or before block `block_num`.
+async fn rewind(config: Config, block_num: u64) -> Result<()> {
+    let mut db_loader = DBLoader::new(
+        config.postgres_dsn.clone(),
+        config.schema.clone(),
+        config.postgres_max_connections as usize,
+    )
+    .await?;
+    db_loader.set_up_cursor_table().await?;
+    db_loader.set_up_history_table().await?;
+    db_loader.load_tables().await?;
+    let package = package::read_package(&config.package_file_name)?;
+    let module_hash = package::module_hash(
+        package
+            .modules
+            .as_ref()
+            .ok_or(anyhow!("Failed to find modules in package"))?,
+        &config.module_name,
+    )?;
+    db_loader.set_output_module_name(Some(config.module_name.clone()));
+    let cursor = db_loader.rewind(module_hash, block_num).await?;
+    info!(
+        "Rewound to block {} ({}), streaming resumes from there",
+        cursor.block.num, cursor.block.id
+    );
+
+    Ok(())
+}
+
+/// Prints the modules of package `spkg`, in dependency order, and checks that the
+/// configured module outputs records.
+fn info(config: Config, spkg: &str) -> Result<()> {
+    let info = PackageInfo::new(&package::read_package(spkg)?)?;
+    for meta in info.meta.iter() {
+        println!("Package {} {} {}", meta.name, meta.version, meta.url);
+    }
+    println!();
+
+    for module in info.sorted_modules()? {
+        match &module.kind {
+            ModuleKind::Map { output_type } => {
+                println!("{} (map) -> {}", module.name, output_type)
+            }
+            ModuleKind::Store {
+                update_policy,
+                value_type,
+            } => println!(
+                "{} (store, {}) -> {}",
+                module.name, update_policy, value_type
+            ),
+        }
+        for input in module.inputs.iter() {
+            match input {
+                ModuleInput::Source { r#type } => println!("  <- source {}", r#type),
+                ModuleInput::Map { module_name } => println!("  <- map {}", module_name),
+                ModuleInput::Store { module_name, mode } => {
+                    println!("  <- store {} ({})", module_name, mode)
+                }
+            }
+        }
+        println!("  initial block: {}", module.initial_block);
+        println!(
+            "  binary: #{} {}, {} bytes, entrypoint {}",
+            module.binary_index, module.binary_type, module.binary_size, module.binary_entrypoint
+        );
+        println!("  hash: {}", module.hash);
+        if let Some(doc) = &module.doc {
+            println!("  doc: {}", doc);
+        }
+    }
+
+    if info.proto_files.len() > 0 {
+        println!();
+        println!("Proto files:");
+        for proto_file in info.proto_files.iter() {
+            println!("  {}", proto_file);
+        }
+    }
+
+    if config.module_name.len() > 0 {
+        println!();
+        match info.validate_output_module(&config.module_name, RECORD_CHANGES_TYPE) {
+            Ok(()) => println!("Module {} outputs records", config.module_name),
+            Err(e) => println!("Module {} can't be sunk: {}", config.module_name, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the pending migrations of directory `migrations_dir`, then sets up
+/// the tables used by the sink itself.
+async fn apply_migrations(db_loader: &DBLoader, migrations_dir: &str) -> Result<()> {
+    let migrations = Migration::read_dir(std::path::Path::new(migrations_dir))?;
+    let applied = db_loader.migrate(&migrations).await?;
+    for migration in applied.iter() {
+        info!("Applied migration {}_{}", migration.version, migration.name);
+    }
+    if applied.is_empty() {
+        info!("Schema is up to date");
+    }
+    db_loader.set_up_cursor_table().await?;
+    db_loader.set_up_history_table().await?;
+
+    Ok(())
+}
+
 async fn run(config: Config) -> Result<()> {
     // create a [`DBLoader`] instance
-    let mut db_loader = DBLoader::new(config.postgres_dsn.clone(), config.schema.clone())
-        .expect("Failed to create a DBLoader instance");
-    // set up the db
-    let file_path = std::path::PathBuf::from_str(config.schema_file_name.as_str()).expect(
-        format!(
-            "Failed to parse schema_file_name = {} as a path",
-            config.schema_file_name
-        )
-        .as_str(),
-    );
-    db_loader.setup_schema(file_path).expect(
-        format!(
-            "Failed to set up schema for file name {}",
-            config.schema_file_name
-        )
-        .as_str(),
-    );
+    let mut db_loader = DBLoader::new(
+        config.postgres_dsn.clone(),
+        config.schema.clone(),
+        config.postgres_max_connections as usize,
+    )
+    .await
+    .expect("Failed to create a DBLoader instance");
+    // set up the db, either with migrations or with the schema file
+    if config.migrations_dir.len() > 0 {
+        apply_migrations(&db_loader, &config.migrations_dir).await?;
+    } else if config.schema_file_name.len() > 0 {
+        let file_path = std::path::PathBuf::from_str(config.schema_file_name.as_str()).expect(
+            format!(
+                "Failed to parse schema_file_name = {} as a path",
+                config.schema_file_name
+            )
+            .as_str(),
+        );
+        db_loader.setup_schema(file_path).await.expect(
+            format!(
+                "Failed to set up schema for file name {}",
+                config.schema_file_name
+            )
+            .as_str(),
+        );
+    } else {
+        // tables are created from the record schema file, or from the stream
+        db_loader.set_up_cursor_table().await?;
+        db_loader.set_up_history_table().await?;
+    }
+    check_primary_key_scheme(&db_loader).await?;
     // load all the tables metadata to the [`DBLoader`] instance
-    db_loader.load_tables().expect("Failed to load tables");
+    db_loader.set_block_columns(config.block_columns);
+    db_loader
+        .load_tables()
+        .await
+        .expect("Failed to load tables");
+    if config.record_schema_file.len() > 0 {
+        for definition in read_record_schemas(&config.record_schema_file)? {
+            if db_loader.ensure_table(&definition).await? {
+                info!("Created or altered table {}", definition.name());
+            }
+        }
+    }
+    db_loader.set_flush_strategy(match config.flush_strategy.as_str() {
+        "single" => FlushStrategy::Single,
+        "batch" => FlushStrategy::Batch,
+        "copy" => FlushStrategy::Copy {
+            min_rows: config.copy_min_rows,
+        },
+        s => return Err(anyhow!("Invalid flush strategy {}", s)),
+    })?;
+    db_loader.set_flush_policy(FlushPolicy {
+        max_entries: Some(config.flush_max_entries).filter(|n| *n > 0),
+        every_blocks: Some(config.flush_every_blocks).filter(|n| *n > 0),
+        interval: Some(config.flush_interval_secs)
+            .filter(|n| *n > 0)
+            .map(Duration::from_secs),
+        live_block_age: Some(config.flush_live_block_age_secs)
+            .filter(|n| *n > 0)
+            .map(Duration::from_secs),
+    });
+    if config.live {
+        db_loader.set_history_depth(Some(config.history_depth));
+    }
+    db_loader.set_rewind_depth(Some(config.rewind_depth).filter(|n| *n > 0));
+    db_loader.set_output_module_name(Some(config.module_name.clone()));
+    let protocol_version = match config.substreams_protocol.as_str() {
+        "v1" => ProtocolVersion::V1,
+        "v2" => ProtocolVersion::V2,
+        s => return Err(anyhow!("Invalid substreams protocol {}", s)),
+    };
 
     let mut client = SubstreamsSink::connect(config.firehose_endpoint, &config.package_file_name)
         .await
         .unwrap();
+    client.set_protocol_version(protocol_version);
+    client
+        .get_package_info()?
+        .validate_output_module(&config.module_name, RECORD_CHANGES_TYPE)?;
 
     let (offchain_task_sender, wasm_host, resolver_task) = if !config.resolve_offchain_data {
         (None, None, None)
@@ -149,11 +460,12 @@ async fn run(config: Config) -> Result<()> {
                 .ok_or(anyhow!("Failed to get binary"))?,
         );
 
-        let wasm_host = wasm::Host::spawn_wasm(
-            modules,
-            PgPool::connect(&config.postgres_dsn).await.unwrap(),
-        )
-        .await?;
+        // the WASM host and the resolver share a single connection pool
+        let connection_pool = PgPoolOptions::new()
+            .max_connections(config.postgres_max_connections)
+            .connect(&config.postgres_dsn)
+            .await?;
+        let wasm_host = wasm::Host::spawn_wasm(modules, connection_pool.clone()).await?;
 
         let mut link_resolvers: HashMap<String, Arc<dyn LinkResolver>> = HashMap::new();
         link_resolvers.insert("https".to_string(), Arc::new(HTTPSLinkResolver::new()?));
@@ -166,7 +478,7 @@ async fn run(config: Config) -> Result<()> {
         }
 
         let mut resolver = Resolver::new(
-            &config.postgres_dsn,
+            connection_pool,
             link_resolvers,
             config.max_concurrent_resolver_tasks,
         )
@@ -188,131 +500,270 @@ async fn run(config: Config) -> Result<()> {
         )
     };
 
-    let cursor = db_loader
-        .get_cursor(config.module_name.clone())
-        .or::<Cursor>(Ok(Cursor {
-            cursor: "".to_string(),
-            block: BlockRef {
-                id: config.start_block.to_string(),
-                num: u64::try_from(config.start_block).expect("Invalid start block"),
-            },
-        }))
-        .expect("Failed to get cursor");
-    let mut stream = client
-        .get_stream(
-            &config.module_name,
-            config.start_block,
-            config.end_block,
-            &cursor.cursor,
-            "STEP_IRREVERSIBLE",
-        )
-        .await?
-        .into_inner();
-
-    while let Some(resp) = stream.next().await {
-        match resp.unwrap().message.unwrap() {
-            Message::Data(block_scoped_data) => {
-                let clock = block_scoped_data
-                    .clock
-                    .ok_or(anyhow!("Failed to parse clock"))?;
-                let cursor = Cursor::new(
-                    block_scoped_data.cursor,
-                    BlockRef::new(clock.id, clock.number),
+    // cursors are identified by the hash of the output module, so that streaming
+    // never resumes from the cursor of another version of the module
+    let module_hash = client.get_output_module_hash(&config.module_name)?;
+    info!(
+        "Output module {} has hash {}",
+        config.module_name, module_hash
+    );
+    let cursor = match db_loader.get_cursor(module_hash.clone()).await {
+        Ok(cursor) => cursor,
+        Err(DBError::EmptyQuery(_)) => get_start_cursor(&config, &db_loader, &module_hash).await?,
+        Err(e) => return Err(anyhow!("Couldn't get cursor: {}", e)),
+    };
+    // a blank cursor starts streaming from its block
+    let start_block = if cursor.is_blank() {
+        i64::try_from(cursor.block.num)?
+    } else {
+        config.start_block
+    };
+    // reconnects on transient errors, resuming from the last received block
+    let mut stream = ResilientStream::new(
+        &mut client,
+        &config.module_name,
+        start_block,
+        config.end_block,
+        &cursor.cursor,
+        !config.live,
+        BackoffPolicy {
+            max_retries: Some(config.stream_max_retries),
+            ..Default::default()
+        },
+    );
+
+    let tracker = Arc::new(Mutex::new(ProgressTracker::new(
+        cursor.block.num,
+        config.end_block,
+    )));
+    if config.status_address.len() > 0 {
+        let address = SocketAddr::from_str(&config.status_address)?;
+        let tracker = tracker.clone();
+        tokio::spawn(async move {
+            if let Err(e) = status::serve(address, tracker).await {
+                error!("Error in status server: {}", e);
+            }
+        });
+    }
+    let progress_log_interval = Some(config.progress_log_interval_secs)
+        .filter(|n| *n > 0)
+        .map(Duration::from_secs);
+    let mut last_progress_log = Instant::now();
+
+    // cursor of the last processed block, written on the next flush
+    let mut last_cursor = None;
+    // tables inferred from the records so far, see [`infer_new_columns`]
+    let mut inferred_tables = HashMap::new();
+    while let Some(message) = stream.next().await {
+        let message = message.map_err(|e| {
+            if let StreamError::ModuleFailed {
+                module_name, logs, ..
+            } = &e
+            {
+                for line in logs {
+                    error!("{}: {}", module_name, line);
+                }
+            }
+            anyhow!("Couldn't read stream: {}", e)
+        })?;
+        {
+            let mut progress = tracker.lock().expect("Progress tracker lock is poisoned");
+            progress.on_message(&message);
+            if progress_log_interval
+                .map_or(false, |interval| last_progress_log.elapsed() >= interval)
+            {
+                log_progress(&progress.status());
+                last_progress_log = Instant::now();
+            }
+        }
+
+        match message {
+            StreamMessage::SessionInit(session) => {
+                info!(
+                    "Session {} started at block {}",
+                    session.trace_id, session.resolved_start_block
                 );
+            }
+            StreamMessage::BlockUndoSignal { last_valid_cursor } => {
+                // revert the changes of the blocks after the last valid one
+                let last_valid_block_num = last_valid_cursor.block.num;
+                db_loader
+                    .undo(module_hash.clone(), last_valid_cursor)
+                    .await
+                    .map_err(|e| {
+                        anyhow!("Couldn't undo blocks after {}: {}", last_valid_block_num, e)
+                    })?;
+                last_cursor = None;
+            }
+            StreamMessage::BlockScopedData(block_scoped_data) => {
+                let block_timestamp = block_scoped_data
+                    .timestamp
+                    .and_then(|t| SystemTime::try_from(t).ok());
+                db_loader.start_block(block_scoped_data.cursor.block.clone(), block_timestamp);
+                let cursor = block_scoped_data.cursor;
                 for output in block_scoped_data.outputs {
-                    match output.data.ok_or(anyhow!("Failed to parse block data"))? {
-                        substreams_sink::substreams::pb::module_output::Data::MapOutput(d) => {
-                            let ops: substreams_sink::pb::RecordChanges = decode(&d.value)?;
-                            for op in &ops.record_changes {
-                                let table_name = op.record.clone();
-                                let id = op.id.clone();
-                                let ordinal = op.ordinal;
-                                // get primary key column name
-                                let primary_key_column_name = db_loader
-                                    .get_primary_key_column_name(&table_name)
-                                    .ok_or(anyhow!("Failed to get primary key"))?;
-                                // TODO: is block_height missing?
-                                // clock.number
-                                let primary_key_label =
-                                    format!("{}<{}_{}>", DOMAIN_SEPARATION_LABEL, id, ordinal);
-                                let mut hasher = Blake2s256::new();
-                                hasher.update(primary_key_label);
-                                let primary_key = hasher.finalize();
-                                // convert to hex representation
-                                let primary_key = encode(primary_key.as_slice());
-                                match op.operation {
-                                    1 => {
-                                        // set data initially with primary key
-                                        let mut data = HashMap::from([(
-                                            primary_key_column_name,
-                                            ColumnValue::Text(Text::set_inner(primary_key.clone())),
-                                        )]);
-                                        for field in &op.fields {
-                                            let col_name = field.name.clone();
-                                            assert!(
-                                                field.old_value.is_none(),
-                                                "insert operation is append only"
-                                            );
-                                            let new_value = field
-                                                .new_value
-                                                .as_ref()
-                                                .ok_or(anyhow!("Failed to get field"))?;
-                                            let new_value = match parse_type_of(new_value) {
-                                                ColumnArrayOrValue::Value(v) => v,
-                                                ColumnArrayOrValue::Array(_) => {
-                                                    panic!("Not implemented")
-                                                }
-                                            };
-                                            data.insert(col_name.clone(), new_value);
-                                            if let pb::value::Typed::Offchaindata(request) = field
-                                                .new_value
-                                                .as_ref()
-                                                .ok_or(anyhow!("Failed to get typed value"))?
-                                                .typed
-                                                .to_owned()
-                                                .ok_or(anyhow!("Failed to access typed value"))?
-                                            {
-                                                if let Some(ref offchain_task_sender) =
-                                                    offchain_task_sender
-                                                {
-                                                    offchain_task_sender
-                                                        .send(resolver::Message::Job(ResolveTask {
-                                                            manifest: config.schema.clone(),
-                                                            request,
-                                                            num_retries: 0,
-                                                        }))
-                                                        .await?;
-                                                }
-                                            }
-                                        }
-                                        db_loader
-                                            .insert(table_name, primary_key, data)
-                                            .expect("Failed to insert data in the DB");
-                                    }
-                                    0 | 2 | 3 => {
-                                        unimplemented!("To be implemented!")
-                                    }
-                                    _ => {
-                                        panic!("Invalid proto value for operation enum")
-                                    }
+                    let ops: substreams_sink::pb::RecordChanges = decode(&output.value)?;
+                    for op in &ops.record_changes {
+                        let table_name = op.record.clone();
+                        let definition = if config.auto_create_tables
+                            && (op.operation == 1 || op.operation == 2)
+                        {
+                            infer_new_columns(&mut inferred_tables, op)
+                        } else {
+                            None
+                        };
+                        if let Some(definition) = definition {
+                            let changed =
+                                db_loader.ensure_table(&definition).await.map_err(|e| {
+                                    anyhow!("Couldn't create or alter table {}: {}", table_name, e)
+                                })?;
+                            if changed {
+                                info!("Created or altered table {}", table_name);
+                            }
+                        }
+                        // get primary key column names
+                        let primary_key_column_names = db_loader
+                            .get_primary_key_column_names(&table_name)
+                            .ok_or(DBError::TableNotFound(table_name.clone()))
+                            .with_context(|| {
+                                format!(
+                                    "Failed to get the primary key of record {} of table {}",
+                                    op.id, table_name
+                                )
+                            })?;
+                        // a composite primary key is mapped from the record id
+                        // by the [`DBLoader`], column by column
+                        let primary_key = if primary_key_column_names.len() == 1 {
+                            get_primary_key(&op.id)
+                        } else {
+                            op.id.clone()
+                        };
+                        match op.operation {
+                            1 => {
+                                if op.fields.iter().any(|f| f.old_value.is_some()) {
+                                    return Err(anyhow!(
+                                        "Insert of record {} of table {} has old values, while inserts are append only",
+                                        op.id,
+                                        table_name
+                                    ));
                                 }
+                                // primary key columns are filled in by the [`DBLoader`]
+                                let data = parse_fields(
+                                    &op.fields,
+                                    db_loader.get_tables().get(&table_name),
+                                    config.auto_create_tables,
+                                )
+                                .with_context(|| {
+                                    format!(
+                                        "Failed to parse record {} of table {}",
+                                        op.id, table_name
+                                    )
+                                })?;
+                                send_offchain_tasks(
+                                    &op.fields,
+                                    &config.schema,
+                                    &offchain_task_sender,
+                                )
+                                .await?;
+                                db_loader
+                                    .insert(table_name.clone(), primary_key, data)
+                                    .with_context(|| {
+                                        format!(
+                                            "Failed to insert record {} of table {}",
+                                            op.id, table_name
+                                        )
+                                    })?;
+                            }
+                            2 => {
+                                let data = parse_fields(
+                                    &op.fields,
+                                    db_loader.get_tables().get(&table_name),
+                                    config.auto_create_tables,
+                                )
+                                .with_context(|| {
+                                    format!(
+                                        "Failed to parse record {} of table {}",
+                                        op.id, table_name
+                                    )
+                                })?;
+                                send_offchain_tasks(
+                                    &op.fields,
+                                    &config.schema,
+                                    &offchain_task_sender,
+                                )
+                                .await?;
+                                db_loader
+                                    .update(table_name.clone(), primary_key, data)
+                                    .with_context(|| {
+                                        format!(
+                                            "Failed to update record {} of table {}",
+                                            op.id, table_name
+                                        )
+                                    })?;
+                            }
+                            3 => {
+                                db_loader
+                                    .delete(table_name.clone(), primary_key, HashMap::new())
+                                    .with_context(|| {
+                                        format!(
+                                            "Failed to delete record {} of table {}",
+                                            op.id, table_name
+                                        )
+                                    })?;
+                            }
+                            operation => {
+                                return Err(anyhow!(
+                                    "Invalid operation {} for record {} of table {}",
+                                    operation,
+                                    op.id,
+                                    table_name
+                                ));
                             }
                         }
-                        _ => {}
                     }
-                    // todo: flush is now per module output; it might make more sense per block?
-                    match db_loader.flush(output.name, cursor.clone()) {
-                        Ok(()) => {}
-                        Err(e) => panic!("Couldn't flush operations to postgres: {}", e),
-                    };
                 }
-                // todo: can we flush here per block?
-                // db_loader.flush()
+                // the cursor is written along with the rows, so that
+                // pending blocks are never partially flushed
+                if db_loader.end_block(block_timestamp) {
+                    db_loader
+                        .flush(module_hash.clone(), cursor)
+                        .await
+                        .map_err(|e| anyhow!("Couldn't flush operations to postgres: {}", e))?;
+                    last_cursor = None;
+                } else {
+                    last_cursor = Some(cursor);
+                }
             }
-            _ => {}
+            // module failures end the stream, see [`StreamError::ModuleFailed`]
+            StreamMessage::Progress(_) => {}
+            StreamMessage::SnapshotData {
+                module_name,
+                sent_keys,
+                total_keys,
+            } => {
+                debug!(
+                    "Received {} of {} keys of store {}",
+                    sent_keys, total_keys, module_name
+                );
+            }
+            StreamMessage::SnapshotComplete { .. } | StreamMessage::Other => {}
         }
     }
     info!("Done reading stream");
+    log_progress(
+        &tracker
+            .lock()
+            .expect("Progress tracker lock is poisoned")
+            .status(),
+    );
+
+    // flush the blocks processed since the last flush
+    if let Some(cursor) = last_cursor {
+        db_loader
+            .flush(module_hash.clone(), cursor)
+            .await
+            .map_err(|e| anyhow!("Couldn't flush operations to postgres: {}", e))?;
+    }
 
     if let (Some(offchain_task_sender), Some(resolver_task), Some(wasm_host)) =
         (offchain_task_sender, resolver_task, wasm_host)
@@ -328,63 +779,454 @@ async fn run(config: Config) -> Result<()> {
     Ok(())
 }
 
-fn decode<T: std::default::Default + prost::Message>(
-    buf: &Vec<u8>,
-) -> Result<T, prost::DecodeError> {
-    ::prost::Message::decode(&buf[..])
+/// Gets the cursor from which streaming starts, when no cursor was written for output
+/// module hash `module_hash`. A cursor written for another hash of the same output
+/// module belongs to another version of it, after whose block streaming only resumes
+/// if `allow_module_hash_change` is set, as the changes of both versions may differ.
+/// A cursor written for the module name itself predates module hashes, and is
+/// resumed from. Cursors of other modules, sinking into the same schema, are ignored.
+async fn get_start_cursor(
+    config: &Config,
+    db_loader: &DBLoader,
+    module_hash: &str,
+) -> Result<Cursor> {
+    let latest_cursor = db_loader
+        .get_cursors()
+        .await?
+        .into_iter()
+        .filter(|(id, entry)| {
+            id != module_hash
+                && (entry.module_name.as_ref() == Some(&config.module_name)
+                    || (entry.module_name.is_none() && *id == config.module_name))
+        })
+        .max_by_key(|(_, entry)| entry.cursor.block.num);
+
+    match latest_cursor {
+        None => Ok(Cursor::new(
+            String::new(),
+            BlockRef::new(
+                config.start_block.to_string(),
+                u64::try_from(config.start_block).map_err(|_| anyhow!("Invalid start block"))?,
+            ),
+        )),
+        Some((id, entry)) if id == config.module_name => {
+            warn!(
+                "Cursor at block {} was written for module {} before cursors were identified by module hash, resuming from it",
+                entry.cursor.block.num, config.module_name
+            );
+            Ok(entry.cursor)
+        }
+        Some((id, entry)) if config.allow_module_hash_change => {
+            warn!(
+                "Cursor at block {} was written for module hash {}, while module {} has hash {}, resuming after its block",
+                entry.cursor.block.num, id, config.module_name, module_hash
+            );
+            Ok(Cursor::new(
+                String::new(),
+                BlockRef::new(String::new(), entry.cursor.block.num + 1),
+            ))
+        }
+        Some((id, entry)) => Err(anyhow!(
+            "Cursor at block {} was written for module hash {}, while module {} has hash {}. Set allow_module_hash_change to resume after its block anyway",
+            entry.cursor.block.num,
+            id,
+            config.module_name,
+            module_hash
+        )),
+    }
 }
 
-enum ColumnArrayOrValue {
-    Value(ColumnValue),
-    Array(Vec<ColumnArrayOrValue>),
+/// Logs the progress of the stream, along with the blocks processed by each module.
+fn log_progress(status: &ProgressStatus) {
+    let eta = status
+        .eta_secs
+        .map_or("unknown".to_string(), |secs| format!("{}s", secs));
+    info!(
+        "Processed blocks {} to {} of {}, {:.1} blocks/s, ETA {}",
+        status.start_block, status.current_block, status.end_block, status.blocks_per_sec, eta
+    );
+    for (name, module) in status.modules.iter() {
+        let ranges = module
+            .processed_ranges
+            .iter()
+            .map(|r| format!("{}-{}", r.start_block, r.end_block))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!("Module {} processed blocks [{}]", name, ranges);
+    }
 }
 
-fn parse_type_of(val: &Value) -> ColumnArrayOrValue {
-    let typed = val.typed.as_ref().unwrap();
-    match typed {
-        pb::value::Typed::Int32(i) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Integer(Integer::set_inner(*i)));
+/// Checks that the rows of the schema were written with the current primary key scheme,
+/// so that updates and deletes target the rows they were written to. A schema with
+/// cursors, but no recorded scheme, was written by an earlier version of the sink,
+/// whose keys can not be derived again, as record ordinals are not stored. Such a
+/// schema must be sunk again from scratch.
+async fn check_primary_key_scheme(db_loader: &DBLoader) -> Result<()> {
+    db_loader.set_up_metadata_table().await?;
+    match db_loader.get_metadata(PRIMARY_KEY_SCHEME_KEY).await? {
+        Some(scheme) if scheme == PRIMARY_KEY_SCHEME => Ok(()),
+        Some(scheme) => Err(anyhow!(
+            "Schema {} was written with primary key scheme {}, while this sink uses {}",
+            db_loader.get_schema(),
+            scheme,
+            PRIMARY_KEY_SCHEME
+        )),
+        None if db_loader.get_cursors().await?.is_empty() => {
+            db_loader
+                .set_metadata(PRIMARY_KEY_SCHEME_KEY, PRIMARY_KEY_SCHEME)
+                .await?;
+            Ok(())
         }
-        // TODO: handle integer cast appropriately
-        pb::value::Typed::Uint32(i) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Integer(Integer::set_inner(*i as i32)))
+        None => Err(anyhow!(
+            "Schema {} was written by an earlier version of the sink, whose primary keys also depend on the record ordinal, thus rows can't be updated nor deleted. Sink the package into a new schema instead",
+            db_loader.get_schema()
+        )),
+    }
+}
+
+/// Derives the primary key of a record from its id, for tables with a single
+/// primary key column. The key only depends on the record id, so that later
+/// updates and deletes target the same row.
+fn get_primary_key(id: &str) -> String {
+    let primary_key_label = format!("{}<{}>", DOMAIN_SEPARATION_LABEL, id);
+    let mut hasher = Blake2s256::new();
+    hasher.update(primary_key_label);
+    let primary_key = hasher.finalize();
+    // convert to hex representation
+    encode(primary_key.as_slice())
+}
+
+/// Infers the definition of the table of a record change, merged into the definitions
+/// `inferred` from earlier changes, and returns it if the change brings a table or
+/// column not seen before, so that the table is only created or altered then, rather
+/// than checked against the DB for every change.
+fn infer_new_columns(
+    inferred: &mut HashMap<String, TableDefinition>,
+    change: &pb::RecordChange,
+) -> Option<TableDefinition> {
+    let definition = infer_table(change);
+    match inferred.get_mut(definition.name()) {
+        Some(known)
+            if definition
+                .columns()
+                .iter()
+                .all(|(c, _)| known.column_type(c).is_some()) =>
+        {
+            None
         }
-        pb::value::Typed::Int64(i) => {
-            return ColumnArrayOrValue::Value(ColumnValue::BigInt(BigInt::set_inner(*i)))
+        Some(known) => {
+            for (c, column_type) in definition.columns() {
+                known.add_column(c.clone(), column_type.clone());
+            }
+            Some(known.clone())
         }
-        // TODO: handle integer cast appropriately
-        pb::value::Typed::Uint64(i) => {
-            return ColumnArrayOrValue::Value(ColumnValue::BigInt(BigInt::set_inner(*i as i64)))
+        None => {
+            inferred.insert(definition.name().to_string(), definition.clone());
+            Some(definition)
         }
-        pb::value::Typed::Bigdecimal(b) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Decimal(Decimal::set_inner(
-                BigDecimal::from_str(b.as_str()).unwrap(),
-            )))
+    }
+}
+
+/// Infers the definition of the table of a record change, from the types of its
+/// field values. The table has an `id` text primary key, onto which record ids are
+/// mapped, while fields without a typed value are left out, until they have one.
+fn infer_table(change: &pb::RecordChange) -> TableDefinition {
+    let mut definition = TableDefinition::new(
+        change.record.clone(),
+        vec![("id".to_string(), ColumnType::Text)],
+    );
+    for field in change.fields.iter() {
+        if let Some(column_type) = field.new_value.as_ref().and_then(default_column_type) {
+            definition.add_column(field.name.clone(), column_type);
         }
-        pb::value::Typed::Bigint(b) => {
-            return ColumnArrayOrValue::Value(ColumnValue::BigInt(BigInt::set_inner(
-                b.parse::<i64>().unwrap(),
-            )))
+    }
+    definition
+}
+
+#[derive(Deserialize)]
+/// Declared schema of a record, in a record schema file.
+struct RecordSchema {
+    /// Primary key column names, `id` if not set.
+    #[serde(default)]
+    primary_key: Vec<String>,
+    /// Column names and types, primary key columns being `text` if not listed.
+    columns: BTreeMap<String, String>,
+}
+
+/// Reads the table definitions of a record schema file, which maps record names
+/// to their schema, e.g.
+///
+/// ```toml
+/// [block_meta]
+/// columns = { number = "bigint", hash = "text", parent_hash = "text" }
+/// ```
+///
+/// Columns are declared in name order, after the primary key ones.
+fn read_record_schemas(file_name: &str) -> Result<Vec<TableDefinition>> {
+    let contents = std::fs::read_to_string(file_name)?;
+    let schemas = toml::from_str::<BTreeMap<String, RecordSchema>>(&contents)?;
+
+    schemas
+        .into_iter()
+        .map(|(record, schema)| {
+            let column_type = |c: &str| match schema.columns.get(c) {
+                Some(t) => ColumnType::try_from(t.as_str()),
+                None => Ok(ColumnType::Text),
+            };
+            let primary_key = if schema.primary_key.is_empty() {
+                vec!["id".to_string()]
+            } else {
+                schema.primary_key.clone()
+            };
+            let primary_key = primary_key
+                .into_iter()
+                .map(|c| Ok((c.clone(), column_type(&c)?)))
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut definition = TableDefinition::new(record, primary_key);
+            for (c, t) in schema.columns.iter() {
+                definition.add_column(c.clone(), ColumnType::try_from(t.as_str())?);
+            }
+            Ok(definition)
+        })
+        .collect()
+}
+
+/// Maps each field name to its new value, parsed as a [`ColumnValue`].
+/// Fields without a new value are mapped to `NULL`. With `skip_unknown_nulls`, i.e. when
+/// tables are inferred from the records, `NULL` fields without a column yet are left out,
+/// the column being added once the field has a typed value, see [`infer_table`].
+fn parse_fields(
+    fields: &[pb::Field],
+    columns: Option<&HashMap<String, ColumnType>>,
+    skip_unknown_nulls: bool,
+) -> Result<HashMap<String, ColumnValue>> {
+    let mut data = HashMap::new();
+    for field in fields {
+        let column_type = columns.and_then(|columns| columns.get(&field.name));
+        let new_value = match &field.new_value {
+            Some(new_value) => {
+                to_column_value(parse_type_of(new_value, column_type)?, column_type)?
+            }
+            None => ColumnValue::Null(Null::default()),
+        };
+        if skip_unknown_nulls && column_type.is_none() && matches!(new_value, ColumnValue::Null(_))
+        {
+            continue;
         }
-        pb::value::Typed::String(s) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Text(Text::set_inner(s.clone())))
+        data.insert(field.name.clone(), new_value);
+    }
+    Ok(data)
+}
+
+/// Converts a parsed value into a [`ColumnValue`]. Arrays are expected to be
+/// one-dimensional, with elements of a same type. The type of an empty array
+/// is the one of its column.
+fn to_column_value(
+    value: ColumnArrayOrValue,
+    column_type: Option<&ColumnType>,
+) -> Result<ColumnValue> {
+    let elements = match value {
+        ColumnArrayOrValue::Value(v) => return Ok(v),
+        ColumnArrayOrValue::Array(a) => a
+            .into_iter()
+            .map(|e| match e {
+                ColumnArrayOrValue::Value(v) => Ok(v),
+                ColumnArrayOrValue::Array(_) => {
+                    Err(anyhow!("Multi-dimensional arrays are not supported"))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    if elements.is_empty() {
+        let column_type =
+            column_type.ok_or(anyhow!("Failed to get the column type of an empty array"))?;
+        Ok(ColumnValue::new_array_of(column_type, elements)?)
+    } else {
+        Ok(ColumnValue::new_array(elements)?)
+    }
+}
+
+/// Schedules the resolution of all offchain data referenced by `fields`.
+async fn send_offchain_tasks(
+    fields: &[pb::Field],
+    manifest: &str,
+    offchain_task_sender: &Option<Sender<resolver::Message>>,
+) -> Result<()> {
+    let offchain_task_sender = match offchain_task_sender {
+        Some(offchain_task_sender) => offchain_task_sender,
+        None => return Ok(()),
+    };
+    for field in fields {
+        if let Some(pb::value::Typed::Offchaindata(request)) = field
+            .new_value
+            .as_ref()
+            .and_then(|value| value.typed.to_owned())
+        {
+            offchain_task_sender
+                .send(resolver::Message::Job(ResolveTask {
+                    manifest: manifest.to_string(),
+                    request,
+                    num_retries: 0,
+                }))
+                .await?;
         }
-        pb::value::Typed::Bytes(b) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Binary(Binary::set_inner(b.clone())))
+    }
+    Ok(())
+}
+
+fn decode<T: std::default::Default + prost::Message>(
+    buf: &Vec<u8>,
+) -> Result<T, prost::DecodeError> {
+    ::prost::Message::decode(&buf[..])
+}
+
+enum ColumnArrayOrValue {
+    Value(ColumnValue),
+    Array(Vec<ColumnArrayOrValue>),
+}
+
+/// Gets the type of a column able to hold a value, used when the column type is unknown.
+/// `NULL` values, as well as arrays without typed elements, have no such type.
+fn default_column_type(val: &Value) -> Option<ColumnType> {
+    Some(match val.typed.as_ref()? {
+        pb::value::Typed::Int32(_) => ColumnType::Integer,
+        pb::value::Typed::Uint32(_) | pb::value::Typed::Int64(_) => ColumnType::BigInt,
+        pb::value::Typed::Uint64(_) | pb::value::Typed::Bigint(_) => ColumnType::Numeric,
+        pb::value::Typed::Bigdecimal(_) => ColumnType::Decimal,
+        pb::value::Typed::String(_) | pb::value::Typed::Offchaindata(_) => ColumnType::Text,
+        pb::value::Typed::Bytes(_) => ColumnType::Binary,
+        pb::value::Typed::Bool(_) => ColumnType::Bool,
+        pb::value::Typed::Json(_) => ColumnType::Jsonb,
+        pb::value::Typed::Array(a) => {
+            let element_type = a.value.iter().find_map(default_column_type)?;
+            return ColumnType::array_of(&element_type);
         }
-        pb::value::Typed::Bool(b) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Bool(Bool::set_inner(b.clone())))
+        pb::value::Typed::Null(_) => return None,
+    })
+}
+
+/// Parses a value into a [`ColumnValue`]. Numeric values are coerced without loss
+/// into the type of their column, if known, or into a type able to hold them otherwise
+/// (see [`default_column_type`]).
+fn parse_type_of(val: &Value, column_type: Option<&ColumnType>) -> Result<ColumnArrayOrValue> {
+    let typed = match val.typed.as_ref() {
+        Some(typed) => typed,
+        None => {
+            return Ok(ColumnArrayOrValue::Value(
+                ColumnValue::Null(Null::default()),
+            ))
         }
+    };
+    let default_type = default_column_type(val);
+    let numeric_type = column_type
+        .or(default_type.as_ref())
+        .unwrap_or(&ColumnType::Numeric);
+    let numeric = |value: BigDecimal| ColumnValue::from_numeric(value, numeric_type);
+    let value = match typed {
+        pb::value::Typed::Int32(i) => numeric(BigDecimal::from(*i))?,
+        pb::value::Typed::Uint32(i) => numeric(BigDecimal::from(*i))?,
+        pb::value::Typed::Int64(i) => numeric(BigDecimal::from(*i))?,
+        pb::value::Typed::Uint64(i) => numeric(BigDecimal::from(*i))?,
+        pb::value::Typed::Bigdecimal(b) => ColumnValue::parse_numeric(b, numeric_type)?,
+        pb::value::Typed::Bigint(b) => ColumnValue::parse_numeric(b, numeric_type)?,
+        pb::value::Typed::String(s) => ColumnValue::Text(Text::set_inner(s.clone())),
+        pb::value::Typed::Bytes(b) => ColumnValue::Binary(Binary::set_inner(b.clone())),
+        pb::value::Typed::Bool(b) => ColumnValue::Bool(Bool::set_inner(*b)),
         pb::value::Typed::Array(a) => {
-            return ColumnArrayOrValue::Array(
+            let element_type = column_type.and_then(|t| t.element_type());
+            return Ok(ColumnArrayOrValue::Array(
                 a.value
                     .iter()
-                    .map(parse_type_of)
-                    .collect::<Vec<ColumnArrayOrValue>>(),
-            )
+                    .map(|v| parse_type_of(v, element_type.as_ref()))
+                    .collect::<Result<Vec<ColumnArrayOrValue>>>()?,
+            ));
+        }
+        pb::value::Typed::Offchaindata(d) => ColumnValue::Text(Text::set_inner(d.uri.clone())),
+        // converted into the type of its column, e.g. json, when scheduled
+        pb::value::Typed::Json(j) => ColumnValue::parse_type(ColumnType::Jsonb, j.clone())
+            .map_err(|_| anyhow!("Failed to parse json value: {}", j))?,
+        pb::value::Typed::Null(_) => ColumnValue::Null(Null::default()),
+    };
+    Ok(ColumnArrayOrValue::Value(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, typed: Option<pb::value::Typed>) -> pb::Field {
+        pb::Field {
+            name: name.to_string(),
+            new_value: Some(Value { typed }),
+            old_value: None,
         }
-        pb::value::Typed::Offchaindata(d) => {
-            return ColumnArrayOrValue::Value(ColumnValue::Text(Text::set_inner(d.uri.clone())))
+    }
+
+    fn insert(fields: Vec<pb::Field>) -> pb::RecordChange {
+        pb::RecordChange {
+            record: "post".to_string(),
+            id: "1".to_string(),
+            ordinal: 0,
+            operation: 1,
+            fields,
         }
     }
+
+    #[test]
+    fn it_works_infer_null_then_typed_field() {
+        let mut inferred = HashMap::new();
+
+        // the image column is unknown until the field has a typed value
+        let change = insert(vec![
+            field("content", Some(pb::value::Typed::String("gm".to_string()))),
+            field("image", Some(pb::value::Typed::Null(pb::Null {}))),
+        ]);
+        let definition = infer_new_columns(&mut inferred, &change).unwrap();
+        assert_eq!(definition.column_type("image"), None);
+        let columns = definition
+            .columns()
+            .iter()
+            .cloned()
+            .collect::<HashMap<_, _>>();
+        let data = parse_fields(&change.fields, Some(&columns), true).unwrap();
+        assert_eq!(
+            data,
+            HashMap::from([(
+                "content".to_string(),
+                ColumnValue::Text(Text::set_inner("gm".to_string()))
+            )])
+        );
+        // without inferred tables, the NULL value is kept for the declared schema
+        assert!(parse_fields(&change.fields, Some(&columns), false)
+            .unwrap()
+            .contains_key("image"));
+
+        let change = insert(vec![
+            field("content", Some(pb::value::Typed::String("gn".to_string()))),
+            field(
+                "image",
+                Some(pb::value::Typed::String("ipfs://".to_string())),
+            ),
+        ]);
+        let definition = infer_new_columns(&mut inferred, &change).unwrap();
+        assert_eq!(definition.column_type("image"), Some(&ColumnType::Text));
+        let columns = definition
+            .columns()
+            .iter()
+            .cloned()
+            .collect::<HashMap<_, _>>();
+        let data = parse_fields(&change.fields, Some(&columns), true).unwrap();
+        assert_eq!(
+            data.get("image"),
+            Some(&ColumnValue::Text(Text::set_inner("ipfs://".to_string())))
+        );
+
+        // once known, the column takes NULL values
+        let change = insert(vec![field("image", None)]);
+        assert!(infer_new_columns(&mut inferred, &change).is_none());
+        let data = parse_fields(&change.fields, Some(&columns), true).unwrap();
+        assert_eq!(data.get("image"), Some(&ColumnValue::Null(Null::default())));
+    }
 }
@@ -0,0 +1,58 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use substreams_sink::ProgressTracker;
+
+/// Serves the progress of the stream as JSON, on `GET /status`.
+pub async fn serve(address: SocketAddr, tracker: Arc<Mutex<ProgressTracker>>) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let tracker = tracker.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let tracker = tracker.clone();
+                async move { Ok::<_, Infallible>(respond(&request, &tracker)) }
+            }))
+        }
+    });
+
+    info!("Serving the stream status on http://{}/status", address);
+    Server::try_bind(&address)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+fn respond(request: &Request<Body>, tracker: &Mutex<ProgressTracker>) -> Response<Body> {
+    if request.uri().path() != "/status" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+
+    let status = tracker
+        .lock()
+        .expect("Progress tracker lock is poisoned")
+        .status();
+    match serde_json::to_string(&status) {
+        Ok(json) => {
+            let mut response = Response::new(Body::from(json));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            response
+        }
+        Err(e) => {
+            let mut response = Response::new(Body::from(e.to_string()));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
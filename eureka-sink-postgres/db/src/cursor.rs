@@ -1,76 +1,267 @@
-use diesel::{sql_query, PgConnection, QueryableByName, RunQueryDsl};
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use diesel::{sql_query, QueryableByName};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncPgConnection, RunQueryDsl};
 use substreams_sink::{BlockRef, Cursor};
 
-use crate::{db_loader::DBLoader, error::DBError};
+use crate::{
+    db_loader::{DBLoader, BLOCK_NUM_COLUMN},
+    error::DBError,
+    history::revert_history,
+    sql_types::ColumnType,
+};
+
+/// Name of the table recording every cursor written, per block, in the current schema.
+pub const CURSOR_HISTORY_TABLE: &str = "cursor_history";
+
+#[derive(QueryableByName, Clone)]
+struct CursorRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    id: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    cursor: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    block_num: i64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    block_id: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    module_name: Option<String>,
+}
+
+/// Entry of the `cursors` table, see [`CursorLoader::get_cursors`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorEntry {
+    /// Name of the output module the cursor was written for, unknown for cursors
+    /// written before module names were recorded.
+    pub module_name: Option<String>,
+    pub cursor: Cursor,
+}
+
+impl TryFrom<CursorRow> for Cursor {
+    type Error = DBError;
+
+    fn try_from(row: CursorRow) -> Result<Self, DBError> {
+        Ok(Cursor {
+            cursor: row.cursor,
+            block: BlockRef {
+                id: row.block_id,
+                num: u64::try_from(row.block_num)
+                    .map_err(|e| DBError::InvalidColumnDataType(e.to_string()))?,
+            },
+        })
+    }
+}
 
 /// Trait to apply changes to the cursors table directly
+#[async_trait]
 pub trait CursorLoader {
     /// Given the current state of the DB, gets the correct [`Cursor`] instance
     /// for the given `id = output_module_hash`.
-    fn get_cursor(&mut self, output_module_hash: String) -> Result<Cursor, DBError>;
+    async fn get_cursor(&self, output_module_hash: String) -> Result<Cursor, DBError>;
+    /// Gets all the cursors of the `cursors` table, by `output_module_hash`, along with
+    /// the name of their output module, e.g. to find the cursors written for an earlier
+    /// version of a module.
+    async fn get_cursors(&self) -> Result<HashMap<String, CursorEntry>, DBError>;
+    /// Gets the most recent [`Cursor`] written for `output_module_hash` at or before
+    /// block `block_num`, from the `cursor_history` table.
+    async fn get_cursor_at(
+        &self,
+        output_module_hash: String,
+        block_num: u64,
+    ) -> Result<Cursor, DBError>;
+    /// Rewinds the state of the DB to the most recent cursor written at or before block
+    /// `block_num`, in a single transaction, and returns that cursor, from which streaming
+    /// resumes. Changes recorded in the `history` table after its block are reverted,
+    /// which restores the rows updated or deleted since, and deletes the rows inserted
+    /// since. If the changes of a block flushed after the cursor were not recorded (see
+    /// [`DBLoader::set_history_depth`]), the rows still written after its block, according
+    /// to their [`BLOCK_NUM_COLUMN`] column, are deleted instead, once the recorded
+    /// changes are reverted. Thus, rows inserted before the block, but updated after it
+    /// without a recorded history, are deleted as well. If a table of records has no
+    /// [`BLOCK_NUM_COLUMN`] column, [`DBError::HistoryNotRecorded`] is returned and the
+    /// DB is left untouched. Pending operations are discarded.
+    async fn rewind(
+        &mut self,
+        output_module_hash: String,
+        block_num: u64,
+    ) -> Result<Cursor, DBError>;
     /// Updates the current state of the `cursors` table, given an `output_module_hash`
     /// value and a [`Cursor`] instance.
-    fn update_cursor_query(
+    async fn update_cursor_query(
         schema: &str,
         module_hash: String,
         cursor: Cursor,
-        conn: &mut PgConnection,
+        conn: &mut AsyncPgConnection,
     ) -> Result<usize, DBError>;
-    /// Writes a new entry to the `cursors` table, given an `output_module_hash` value
-    /// and a [`Cursor`] instance.
-    fn write_cursor(
+    /// Writes a new entry to the `cursors` table, given an `output_module_hash` value,
+    /// the name of the output module, if known, and a [`Cursor`] instance. The cursor is recorded in the `cursor_history` table
+    /// as well, along with whether the changes flushed up to it were recorded in the
+    /// `history` table, and the cursors written before block `keep_from_block` are pruned.
+    async fn write_cursor(
         schema: &str,
         module_hash: String,
+        module_name: Option<String>,
         cursor: Cursor,
-        conn: &mut PgConnection,
+        history_recorded: bool,
+        keep_from_block: u64,
+        conn: &mut AsyncPgConnection,
     ) -> Result<usize, DBError>;
 }
 
+#[async_trait]
 impl CursorLoader for DBLoader {
-    fn get_cursor(&mut self, output_module_hash: String) -> Result<Cursor, DBError> {
-        #[derive(QueryableByName, Clone)]
-        struct CursorRow {
-            #[allow(dead_code)]
-            #[diesel(sql_type = diesel::sql_types::Text)]
-            id: String,
-            #[diesel(sql_type = diesel::sql_types::Text)]
-            cursor: String,
-            #[diesel(sql_type = diesel::sql_types::BigInt)]
-            block_num: i64,
-            #[diesel(sql_type = diesel::sql_types::Text)]
-            block_id: String,
-        }
-
+    async fn get_cursor(&self, output_module_hash: String) -> Result<Cursor, DBError> {
         let query = format!(
-            "SELECT id, cursor, block_num, block_id FROM {}.cursors WHERE id = $1",
+            "SELECT id, cursor, block_num, block_id, module_name FROM {}.cursors WHERE id = $1",
             self.get_schema(),
         );
         let cursor_rows = sql_query(query)
             .bind::<diesel::sql_types::Text, _>(output_module_hash.clone())
-            .load::<CursorRow>(self.connection())
+            .load::<CursorRow>(&mut self.connection().await?)
+            .await
             .map_err(|e| DBError::DieselError(e))?;
 
         // Selecting on the primary key defines a unique mapping,
         let cursor_row = cursor_rows
-            .first()
-            .ok_or(DBError::EmptyQuery(output_module_hash))?
-            .clone();
+            .into_iter()
+            .next()
+            .ok_or(DBError::EmptyQuery(output_module_hash))?;
 
-        Ok(Cursor {
-            cursor: cursor_row.cursor,
-            block: BlockRef {
-                id: cursor_row.block_id,
-                num: u64::try_from(cursor_row.block_num)
-                    .map_err(|e| DBError::InvalidColumnDataType(e.to_string()))?,
-            },
-        })
+        Cursor::try_from(cursor_row)
+    }
+
+    async fn get_cursors(&self) -> Result<HashMap<String, CursorEntry>, DBError> {
+        let query = format!(
+            "SELECT id, cursor, block_num, block_id, module_name FROM {}.cursors",
+            self.get_schema(),
+        );
+        sql_query(query)
+            .load::<CursorRow>(&mut self.connection().await?)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let id = row.id.clone();
+                let module_name = row.module_name.clone();
+                let cursor = Cursor::try_from(row)?;
+                Ok((
+                    id,
+                    CursorEntry {
+                        module_name,
+                        cursor,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    async fn get_cursor_at(
+        &self,
+        output_module_hash: String,
+        block_num: u64,
+    ) -> Result<Cursor, DBError> {
+        let query = format!(
+            "SELECT id, cursor, block_num, block_id, module_name FROM {}.{} WHERE id = $1 AND block_num <= $2 ORDER BY block_num DESC LIMIT 1",
+            self.get_schema(),
+            CURSOR_HISTORY_TABLE
+        );
+        let cursor_row = sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(output_module_hash)
+            .bind::<diesel::sql_types::BigInt, _>(block_num as i64)
+            .load::<CursorRow>(&mut self.connection().await?)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(DBError::CursorNotFound(block_num))?;
+
+        Cursor::try_from(cursor_row)
     }
 
-    fn update_cursor_query(
+    async fn rewind(
+        &mut self,
+        output_module_hash: String,
+        block_num: u64,
+    ) -> Result<Cursor, DBError> {
+        let cursor = self
+            .get_cursor_at(output_module_hash.clone(), block_num)
+            .await?;
+        let schema = self.get_schema().clone();
+        let module_name = self.get_output_module_name().clone();
+        let primary_keys = self.get_primary_keys();
+        let deletes = rewind_queries(&schema, self.get_tables());
+
+        // pending operations belong to blocks which are rewound
+        self.reset()?;
+
+        let last_valid_cursor = cursor.clone();
+        self.connection()
+            .await?
+            .build_transaction()
+            .read_write()
+            .run::<_, DBError, _>(|conn| {
+                async move {
+                    let last_valid_block_num = last_valid_cursor.block.num;
+                    // without the history of every later block, rows written after the
+                    // block are deleted, according to their block number
+                    let unrecorded = sql_query(unrecorded_history_query(&schema))
+                        .bind::<diesel::sql_types::Text, _>(output_module_hash.clone())
+                        .bind::<diesel::sql_types::BigInt, _>(last_valid_block_num as i64)
+                        .load::<CursorRow>(conn)
+                        .await?;
+                    let deletes = match (unrecorded.into_iter().next(), deletes) {
+                        (None, _) => Vec::new(),
+                        (Some(_), Some(deletes)) => deletes,
+                        (Some(row), None) => {
+                            return Err(DBError::HistoryNotRecorded {
+                                block_num: last_valid_block_num,
+                                unrecorded_block_num: u64::try_from(row.block_num)
+                                    .unwrap_or_default(),
+                            })
+                        }
+                    };
+
+                    revert_history(
+                        &schema,
+                        &primary_keys,
+                        &output_module_hash,
+                        last_valid_block_num,
+                        conn,
+                    )
+                    .await?;
+                    for delete in deletes {
+                        sql_query(delete)
+                            .bind::<diesel::sql_types::BigInt, _>(last_valid_block_num as i64)
+                            .execute(conn)
+                            .await?;
+                    }
+                    // update the cursors table, which prunes the later cursor history
+                    Self::write_cursor(
+                        &schema,
+                        output_module_hash,
+                        module_name,
+                        last_valid_cursor,
+                        true,
+                        0,
+                        conn,
+                    )
+                    .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        self.reset_flush_state();
+
+        Ok(cursor)
+    }
+
+    async fn update_cursor_query(
         schema: &str,
         module_hash: String,
         cursor: Cursor,
-        conn: &mut PgConnection,
+        conn: &mut AsyncPgConnection,
     ) -> Result<usize, DBError> {
         let query = format!(
             "UPDATE {}.cursors SET cursor = $1, block_num = $2, block_id = $3 WHERE id = $4",
@@ -82,28 +273,143 @@ impl CursorLoader for DBLoader {
             .bind::<diesel::sql_types::Text, _>(cursor.block.id)
             .bind::<diesel::sql_types::Text, _>(module_hash)
             .execute(conn)
+            .await
             .map_err(|e| DBError::DieselError(e))
     }
 
-    fn write_cursor(
+    async fn write_cursor(
         schema: &str,
         module_hash: String,
+        module_name: Option<String>,
         cursor: Cursor,
-        conn: &mut PgConnection,
+        history_recorded: bool,
+        keep_from_block: u64,
+        conn: &mut AsyncPgConnection,
     ) -> Result<usize, DBError> {
-        let query = format!(
-            "
-                INSERT INTO {}.cursors (id, cursor, block_num, block_id) VALUES ($1, $2, $3, $4)
-                    ON CONFLICT (id) DO UPDATE SET cursor=$2, block_num=$3, block_id=$3 
-            ",
-            schema
-        );
-        sql_query(query)
+        sql_query(write_cursor_query(schema))
             .bind::<diesel::sql_types::Text, _>(module_hash)
             .bind::<diesel::sql_types::Text, _>(cursor.cursor)
             .bind::<diesel::sql_types::BigInt, _>(cursor.block.num as i64)
             .bind::<diesel::sql_types::Text, _>(cursor.block.id)
+            .bind::<diesel::sql_types::Bool, _>(history_recorded)
+            .bind::<diesel::sql_types::BigInt, _>(keep_from_block as i64)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(module_name)
             .execute(conn)
+            .await
             .map_err(|e| DBError::DieselError(e))
     }
 }
+
+/// Query to upsert an entry of the `cursors` table, with binds
+/// `(id, cursor, block_num, block_id, history, keep_from_block, module_name)`. The cursor is recorded
+/// in the `cursor_history` table as well, where cursors written for later blocks, which
+/// were undone or rewound, and cursors written before block `keep_from_block`, are
+/// pruned. A cursor recorded again only keeps its `history` flag if both writes set it.
+pub(crate) fn write_cursor_query(schema: &str) -> String {
+    format!(
+        "
+            WITH pruned AS (
+                DELETE FROM {schema}.{history} WHERE id = $1 AND (block_num > $3 OR block_num < $6)
+            ), recorded AS (
+                INSERT INTO {schema}.{history} (id, cursor, block_num, block_id, history, module_name) VALUES ($1, $2, $3, $4, $5, $7)
+                    ON CONFLICT (id, block_num) DO UPDATE SET cursor=$2, block_id=$4, history={history}.history AND $5, module_name=$7, written_at=now()
+            )
+            INSERT INTO {schema}.cursors (id, cursor, block_num, block_id, module_name) VALUES ($1, $2, $3, $4, $7)
+                ON CONFLICT (id) DO UPDATE SET cursor=$2, block_num=$3, block_id=$4, module_name=$7
+        ",
+        schema = schema,
+        history = CURSOR_HISTORY_TABLE
+    )
+}
+
+/// Block from which cursors are kept in the `cursor_history` table, once a cursor is
+/// written for block `block_num`, so that the DB can be rewound to them for `depth`
+/// blocks (see [`DBLoader::get_cursor_history_depth`]). Without a depth, only the
+/// latest cursor is kept.
+pub(crate) fn keep_cursors_from_block(block_num: u64, depth: Option<u64>) -> u64 {
+    depth.map_or(block_num, |depth| block_num.saturating_sub(depth))
+}
+
+/// Builds the queries deleting the rows written after a block, whose number is bound
+/// as `$1`, from each table of records in `tables`, according to their
+/// [`BLOCK_NUM_COLUMN`] column. Outputs `None` if a table has no such column.
+pub(crate) fn rewind_queries(
+    schema: &str,
+    tables: &HashMap<String, HashMap<String, ColumnType>>,
+) -> Option<Vec<String>> {
+    let mut table_names = tables
+        .keys()
+        .filter(|t| !DBLoader::is_internal_table(t))
+        .collect::<Vec<_>>();
+    table_names.sort();
+    table_names
+        .into_iter()
+        .map(|t| {
+            tables[t].contains_key(BLOCK_NUM_COLUMN).then(|| {
+                format!(
+                    "DELETE FROM {}.{} WHERE {} > $1",
+                    schema, t, BLOCK_NUM_COLUMN
+                )
+            })
+        })
+        .collect()
+}
+
+/// Query selecting the cursors written for id `$1` after block `$2`, whose changes
+/// were not recorded in the `history` table.
+pub(crate) fn unrecorded_history_query(schema: &str) -> String {
+    format!(
+        "SELECT id, cursor, block_num, block_id, module_name FROM {}.{} WHERE id = $1 AND block_num > $2 AND NOT history ORDER BY block_num LIMIT 1",
+        schema, CURSOR_HISTORY_TABLE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_keep_cursors_from_block() {
+        assert_eq!(keep_cursors_from_block(1500, Some(1000)), 500);
+        assert_eq!(keep_cursors_from_block(500, Some(1000)), 0);
+        assert_eq!(keep_cursors_from_block(1500, None), 1500);
+    }
+
+    #[test]
+    fn it_works_rewind_queries() {
+        let mut tables = HashMap::from([
+            (
+                "posts".to_string(),
+                HashMap::from([
+                    ("id".to_string(), ColumnType::Text),
+                    (BLOCK_NUM_COLUMN.to_string(), ColumnType::BigInt),
+                ]),
+            ),
+            (
+                "cursors".to_string(),
+                HashMap::from([("id".to_string(), ColumnType::Text)]),
+            ),
+            (
+                CURSOR_HISTORY_TABLE.to_string(),
+                HashMap::from([("block_num".to_string(), ColumnType::BigInt)]),
+            ),
+        ]);
+        assert_eq!(
+            rewind_queries("my_schema", &tables).unwrap(),
+            vec!["DELETE FROM my_schema.posts WHERE _block_num > $1"]
+        );
+        tables.insert(
+            "profiles".to_string(),
+            HashMap::from([("id".to_string(), ColumnType::Text)]),
+        );
+        assert_eq!(rewind_queries("my_schema", &tables), None);
+    }
+
+    #[test]
+    fn it_works_unrecorded_history_query() {
+        assert_eq!(
+            unrecorded_history_query("my_schema"),
+            "SELECT id, cursor, block_num, block_id, module_name FROM my_schema.cursor_history WHERE id = $1 AND block_num > $2 AND NOT history ORDER BY block_num LIMIT 1"
+        );
+    }
+}
@@ -6,14 +6,20 @@ pub enum DBError {
     DieselError(#[from] diesel::result::Error),
     #[error("ConnectionError: {0}")]
     ConnectionError(#[from] diesel::ConnectionError),
+    #[error("PostgresError: {0}")]
+    PostgresError(#[from] tokio_postgres::Error),
+    #[error("The copy flush strategy connects without TLS, while the DSN requires TLS")]
+    TlsNotSupportedForCopy,
+    #[error("PoolError: {0}")]
+    PoolError(String),
     #[error("Invalid cursor columns")]
     InvalidCursorColumns,
     #[error("Invalid cursor column type")]
     InvalidCursorColumnType,
     #[error("Invalid schema path: {0}")]
     InvalidSchemaPath(#[from] std::io::Error),
-    #[error("Invalid field type")]
-    InvalidFieldType,
+    #[error("Invalid field type: {0}")]
+    InvalidFieldType(String),
     #[error("Invalid DNS parsing: {0}")]
     InvalidDSNParsing(#[from] dsn::ParseError),
     #[error("Table {0} not found")]
@@ -25,8 +31,19 @@ pub enum DBError {
         table_name: String,
         primary_key: String,
     },
-    #[error("Column {0} not found")]
-    ColumnNotFound(String),
+    #[error(
+        "Primary key {primary_key} does not map onto the {columns} primary key columns of table {table_name}"
+    )]
+    InvalidPrimaryKey {
+        table_name: String,
+        primary_key: String,
+        columns: usize,
+    },
+    #[error("Column {column_name} of table {table_name} not found")]
+    ColumnNotFound {
+        table_name: String,
+        column_name: String,
+    },
     #[error("Failed to parse value {0}")]
     FailedParseString(String),
     #[error("Failed to execute query: {query} with error: {error}")]
@@ -35,6 +52,43 @@ pub enum DBError {
     EmptyQuery(String),
     #[error("Invalid column data type: {0}")]
     InvalidColumnDataType(String),
+    #[error("Invalid value for column {column_name} of table {table_name}: {error}")]
+    InvalidColumnValue {
+        table_name: String,
+        column_name: String,
+        error: String,
+    },
+    #[error("Column {column_name} of table {table_name} can not be null")]
+    NullValueInNotNullColumn {
+        table_name: String,
+        column_name: String,
+    },
+    #[error("Value {value} is out of range for column type {column_type}")]
+    ValueOutOfRange { value: String, column_type: String },
+    #[error("Invalid history operation type: {0}")]
+    InvalidHistoryOperation(String),
+    #[error("No cursor written at or before block {0}")]
+    CursorNotFound(u64),
+    #[error("Changes of block {unrecorded_block_num} were not recorded in the history, nor tracked in block columns, the DB can not be rewound to block {block_num}")]
+    HistoryNotRecorded {
+        block_num: u64,
+        unrecorded_block_num: u64,
+    },
+    #[error("Column {column_name} of table {table_name} has an invalid type for a block column")]
+    InvalidBlockColumnType {
+        table_name: String,
+        column_name: String,
+    },
+    #[error("Invalid migration file name {0}, expected <version>_<name>.sql")]
+    InvalidMigrationName(String),
+    #[error("Duplicate migration version {0}")]
+    DuplicateMigrationVersion(i64),
+    #[error("Applied migration {0} not found")]
+    MigrationNotFound(i64),
+    #[error("Migration {version}_{name} was edited after being applied")]
+    MigrationChecksumMismatch { version: i64, name: String },
+    #[error("Migration {version} is older than the latest applied migration {latest}")]
+    MigrationOutOfOrder { version: i64, latest: i64 },
 }
 
 // impl From<DBError> for diesel::result::Error {
@@ -1,57 +1,396 @@
-use diesel::{sql_query, RunQueryDsl};
+use std::{collections::BTreeMap, collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use diesel::{pg::Pg, sql_query};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncPgConnection};
+use futures_util::{pin_mut, SinkExt};
 use substreams_sink::Cursor;
 
-use crate::{cursor::CursorLoader, db_loader::DBLoader, error::DBError};
+use crate::{
+    cursor::{keep_cursors_from_block, write_cursor_query, CursorLoader},
+    db_loader::DBLoader,
+    error::DBError,
+    history::with_history,
+    operation::{Operation, OperationType, Query},
+};
+
+/// PostgreSQL limits the number of bind parameters of a single query.
+const MAX_BIND_PARAMETERS: usize = 65535;
 
 /// Interface to flush changes to a [`DBLoader`] instance.
+#[async_trait]
 pub trait FlushLoader {
-    fn flush(&mut self, output_module_hash: String, cursor: Cursor) -> Result<(), DBError>;
+    async fn flush(&mut self, output_module_hash: String, cursor: Cursor) -> Result<(), DBError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Strategy used to write the pending operations of a [`DBLoader`] on flush.
+pub enum FlushStrategy {
+    /// Executes one query per operation.
+    Single,
+    /// Groups inserts into multi-row `INSERT` queries, per table.
+    #[default]
+    Batch,
+    /// Streams inserts into each table with `COPY ... FROM STDIN`, if the pending
+    /// operations are all inserts and there are at least `min_rows` of them.
+    /// Otherwise, it falls back to [`FlushStrategy::Batch`].
+    Copy { min_rows: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Policy deciding when the pending operations of a [`DBLoader`] are flushed.
+/// Flushes happen at block boundaries, as soon as any of the set conditions holds.
+pub struct FlushPolicy {
+    /// Flush once the number of pending entries reaches this value.
+    pub max_entries: Option<u64>,
+    /// Flush once this number of blocks has been processed since the last flush.
+    pub every_blocks: Option<u64>,
+    /// Flush once this wall-clock interval has elapsed since the last flush.
+    pub interval: Option<Duration>,
+    /// Flush every block whose timestamp is at most this old, that is,
+    /// once we are close to the chain head.
+    pub live_block_age: Option<Duration>,
+}
+
+impl Default for FlushPolicy {
+    /// Flushes every block.
+    fn default() -> Self {
+        Self {
+            max_entries: None,
+            every_blocks: Some(1),
+            interval: None,
+            live_block_age: None,
+        }
+    }
+}
+
+impl FlushPolicy {
+    /// Checks if pending operations should be flushed, given the number of pending
+    /// entries and blocks, the time elapsed since the last flush and the age of
+    /// the last processed block, if known.
+    pub fn should_flush(
+        &self,
+        entries_count: u64,
+        pending_blocks: u64,
+        since_last_flush: Duration,
+        block_age: Option<Duration>,
+    ) -> bool {
+        if pending_blocks == 0 {
+            return false;
+        }
+
+        self.max_entries.map_or(false, |max| entries_count >= max)
+            || self.every_blocks.map_or(false, |n| pending_blocks >= n)
+            || self.interval.map_or(false, |i| since_last_flush >= i)
+            || matches!((self.live_block_age, block_age), (Some(max), Some(age)) if age <= max)
+    }
 }
 
 #[allow(dead_code)]
+#[async_trait]
 impl FlushLoader for DBLoader {
-    fn flush(&mut self, output_module_hash: String, cursor: Cursor) -> Result<(), DBError> {
-        let entries = self.entries().clone();
+    async fn flush(&mut self, output_module_hash: String, cursor: Cursor) -> Result<(), DBError> {
+        match self.get_flush_strategy().clone() {
+            FlushStrategy::Copy { min_rows }
+                if self.get_entries_count() >= min_rows
+                    && self.has_only_inserts()
+                    && self.get_history_depth().is_none() =>
+            {
+                self.flush_copy(output_module_hash, cursor).await?
+            }
+            FlushStrategy::Single => {
+                self.flush_queries(output_module_hash, cursor, false)
+                    .await?
+            }
+            _ => self.flush_queries(output_module_hash, cursor, true).await?,
+        }
+
+        // after flushing, we reset our operation entries
+        self.reset()?;
+        self.reset_flush_state();
+
+        Ok(())
+    }
+}
+
+impl DBLoader {
+    /// Resets [`DBLoader`] instance state.
+    pub(crate) fn reset(&mut self) -> Result<(), DBError> {
+        self.entries_mut().iter_mut().for_each(|(_, hm)| hm.clear());
+        self.reset_entries_count();
+
+        Ok(())
+    }
+
+    /// Checks if all pending operations are inserts, which replace no row.
+    fn has_only_inserts(&self) -> bool {
+        self.entries()
+            .values()
+            .flat_map(|ops| ops.values())
+            .all(|op| *op.op_type() == OperationType::Insert && !op.replace())
+    }
+
+    /// Executes all pending operations, and updates the cursor, in a single transaction.
+    /// If `batch` is set, inserts are grouped into multi-row queries. If a history depth
+    /// is set, changes are recorded in the `history` table as well.
+    async fn flush_queries(
+        &self,
+        output_module_hash: String,
+        cursor: Cursor,
+        batch: bool,
+    ) -> Result<(), DBError> {
+        let queries = if batch {
+            Self::build_batch_queries(self.entries())
+        } else {
+            self.entries()
+                .values()
+                .flat_map(|ops| ops.values().flat_map(|op| op.build_queries()))
+                .collect()
+        };
         let schema = self.get_schema().clone();
+        let module_name = self.get_output_module_name().clone();
+        let history_depth = *self.get_history_depth();
+        let keep_from_block =
+            keep_cursors_from_block(cursor.block.num, self.get_cursor_history_depth());
+        let queries = match self.get_history_depth() {
+            Some(depth) => with_history(
+                self.entries(),
+                queries,
+                &schema,
+                &output_module_hash,
+                cursor.block.num,
+                *depth,
+            ),
+            None => queries,
+        };
 
         self.connection()
+            .await?
             .build_transaction()
             .read_write()
-            .run::<_, diesel::result::Error, _>(|conn| {
-                entries.values().for_each(|ops| {
-                    for op in ops.values() {
-                        let query = op.build_query();
+            .run::<_, DBError, _>(|conn| {
+                async move {
+                    for query in queries {
                         // execute the query to the database
-                        sql_query(&query)
-                            .execute(conn)
-                            .map_err(|e| DBError::FailedToExecuteQuery {
-                                query: query,
-                                error: e.to_string(),
-                            })
-                            .expect("Failed to execute query");
+                        Self::execute_query(query, conn).await?;
                     }
-                });
-                // update the cursors table
-                Self::write_cursor(&schema, output_module_hash.clone(), cursor.clone(), conn)
-                    .expect("Failed to upsert in cursors table");
+                    // update the cursors table
+                    Self::write_cursor(
+                        &schema,
+                        output_module_hash,
+                        module_name,
+                        cursor,
+                        history_depth.is_some(),
+                        keep_from_block,
+                        conn,
+                    )
+                    .await?;
 
-                Ok(())
+                    Ok(())
+                }
+                .scope_boxed()
             })
-            .map_err(|e| DBError::DieselError(e))?;
+            .await
+    }
 
-        // after flushing, we reset our operation entries
-        self.reset()?;
+    /// Streams all pending inserts with `COPY ... FROM STDIN`, and updates the cursor,
+    /// in a single transaction.
+    async fn flush_copy(
+        &mut self,
+        output_module_hash: String,
+        cursor: Cursor,
+    ) -> Result<(), DBError> {
+        let schema = self.get_schema().clone();
+        let module_name = self.get_output_module_name().clone();
+        let keep_from_block =
+            keep_cursors_from_block(cursor.block.num, self.get_cursor_history_depth()) as i64;
+        let mut copies = Vec::new();
+        for ops in self.entries().values() {
+            for (columns, ops) in Self::group_inserts(ops.values()) {
+                let statement = format!(
+                    "COPY {}.{} ({}) FROM STDIN",
+                    schema,
+                    ops[0].table_name(),
+                    columns.join(",")
+                );
+                let mut rows = String::new();
+                for op in ops {
+                    let row = columns
+                        .iter()
+                        .map(|c| op.data()[c].to_copy_text())
+                        .collect::<Result<Vec<_>, DBError>>()?;
+                    rows.push_str(&row.join("\t"));
+                    rows.push('\n');
+                }
+                copies.push((statement, rows));
+            }
+        }
+
+        let transaction = self.copy_client().await?.transaction().await?;
+        for (statement, rows) in copies {
+            let writer = transaction.copy_in(statement.as_str()).await?;
+            pin_mut!(writer);
+            writer
+                .send(Bytes::from(rows))
+                .await
+                .map_err(|e| DBError::FailedToExecuteQuery {
+                    query: statement.clone(),
+                    error: e.to_string(),
+                })?;
+            writer.finish().await?;
+        }
+        // update the cursors table, no history is recorded along with `COPY` flushes
+        transaction
+            .execute(
+                write_cursor_query(&schema).as_str(),
+                &[
+                    &output_module_hash,
+                    &cursor.cursor,
+                    &(cursor.block.num as i64),
+                    &cursor.block.id,
+                    &false,
+                    &keep_from_block,
+                    &module_name,
+                ],
+            )
+            .await?;
+        transaction.commit().await?;
 
         Ok(())
     }
+
+    /// Builds the queries for all pending operations, where inserts on the same table
+    /// and columns are grouped into multi-row queries. Rows replaced by inserts are
+    /// deleted first.
+    fn build_batch_queries(entries: &HashMap<String, HashMap<String, Operation>>) -> Vec<Query> {
+        let mut queries = Vec::new();
+        for ops in entries.values() {
+            queries.extend(
+                ops.values()
+                    .filter(|op| op.replace())
+                    .map(|op| op.build_delete_query()),
+            );
+            for (columns, inserts) in Self::group_inserts(ops.values()) {
+                let rows_per_query = (MAX_BIND_PARAMETERS / columns.len().max(1)).max(1);
+                queries.extend(
+                    inserts
+                        .chunks(rows_per_query)
+                        .map(Operation::build_batch_insert_query),
+                );
+            }
+            queries.extend(
+                ops.values()
+                    .filter(|op| *op.op_type() != OperationType::Insert)
+                    .map(|op| op.build_query()),
+            );
+        }
+        queries
+    }
+
+    /// Groups insert operations by their (sorted) column names.
+    fn group_inserts<'a>(
+        ops: impl Iterator<Item = &'a Operation>,
+    ) -> BTreeMap<Vec<String>, Vec<&'a Operation>> {
+        let mut groups: BTreeMap<Vec<String>, Vec<&'a Operation>> = BTreeMap::new();
+        for op in ops.filter(|op| *op.op_type() == OperationType::Insert) {
+            groups.entry(op.column_names()).or_default().push(op);
+        }
+        groups
+    }
+
+    /// Executes a parameterized [`Query`], binding each of its values
+    /// to the corresponding placeholder.
+    pub(crate) async fn execute_query(
+        query: Query,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<usize, DBError> {
+        let mut boxed_query = sql_query(query.sql.clone()).into_boxed::<Pg>();
+        for value in query.binds.iter() {
+            boxed_query = value.bind(boxed_query)?;
+        }
+
+        // called by path, as `RunQueryDsl` methods clash with the `COPY` client ones
+        diesel_async::RunQueryDsl::execute(boxed_query, conn)
+            .await
+            .map_err(|e| DBError::FailedToExecuteQuery {
+                query: query.sql,
+                error: e.to_string(),
+            })
+    }
 }
 
-impl DBLoader {
-    /// Resets [`DBLoader`] instance state.
-    fn reset(&mut self) -> Result<(), DBError> {
-        self.entries_mut().iter_mut().for_each(|(_, hm)| hm.clear());
-        self.reset_entries_count();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_types::{ColumnValue, Sql, Text};
 
-        Ok(())
+    #[test]
+    fn it_works_default_flush_policy() {
+        let policy = FlushPolicy::default();
+        assert!(!policy.should_flush(10, 0, Duration::from_secs(10), None));
+        assert!(policy.should_flush(0, 1, Duration::ZERO, None));
+    }
+
+    #[test]
+    fn it_works_flush_policy() {
+        let policy = FlushPolicy {
+            max_entries: Some(1000),
+            every_blocks: Some(100),
+            interval: Some(Duration::from_secs(30)),
+            live_block_age: Some(Duration::from_secs(60)),
+        };
+        let old_block = Some(Duration::from_secs(3600));
+        assert!(!policy.should_flush(10, 10, Duration::from_secs(1), old_block));
+        assert!(!policy.should_flush(10, 10, Duration::from_secs(1), None));
+        // entries threshold
+        assert!(policy.should_flush(1000, 10, Duration::from_secs(1), old_block));
+        // blocks threshold
+        assert!(policy.should_flush(10, 100, Duration::from_secs(1), old_block));
+        // time threshold
+        assert!(policy.should_flush(10, 10, Duration::from_secs(30), old_block));
+        // near the chain head
+        assert!(policy.should_flush(10, 1, Duration::ZERO, Some(Duration::from_secs(2))));
+    }
+
+    #[test]
+    fn it_works_build_batch_queries_with_replaced_rows() {
+        let insert = |id: &str, replace: bool| {
+            let mut op = Operation::new(
+                "my_schema".to_string(),
+                "posts".to_string(),
+                OperationType::Insert,
+                vec![(
+                    "id".to_string(),
+                    ColumnValue::Text(Text::set_inner(id.to_string())),
+                )],
+                HashMap::from([(
+                    "id".to_string(),
+                    ColumnValue::Text(Text::set_inner(id.to_string())),
+                )]),
+            );
+            op.set_replace(replace);
+            op
+        };
+        // post1 was deleted, then inserted again, within the flush
+        let entries = HashMap::from([(
+            "posts".to_string(),
+            HashMap::from([
+                ("post1".to_string(), insert("post1", true)),
+                ("post2".to_string(), insert("post2", false)),
+            ]),
+        )]);
+
+        let queries = DBLoader::build_batch_queries(&entries);
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0].sql, "DELETE FROM my_schema.posts WHERE id = $1");
+        assert_eq!(
+            queries[0].binds,
+            vec![ColumnValue::Text(Text::set_inner("post1".to_string()))]
+        );
+        assert_eq!(
+            queries[1].sql,
+            "INSERT INTO my_schema.posts (id) VALUES ($1),($2)"
+        );
     }
 }
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use diesel::{sql_query, QueryableByName};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncPgConnection, RunQueryDsl};
+use substreams_sink::Cursor;
+
+use crate::{
+    cursor::CursorLoader,
+    db_loader::DBLoader,
+    error::DBError,
+    operation::{Operation, OperationType, Query},
+    sql_types::{BigInt, ColumnValue, Sql, Text},
+};
+
+/// Name of the table recording the reversible changes of each block,
+/// in the current schema.
+pub const HISTORY_TABLE: &str = "history";
+
+/// Trait to revert the changes of blocks which were undone by a fork, using the
+/// `history` table. Changes are only recorded if a history depth is set on the
+/// [`DBLoader`] (see [`DBLoader::set_history_depth`]).
+#[async_trait]
+pub trait HistoryLoader {
+    /// Reverts all changes recorded for `output_module_hash` for blocks after the block
+    /// of `last_valid_cursor`, and writes the cursor, in a single transaction. Pending
+    /// operations are discarded.
+    async fn undo(
+        &mut self,
+        output_module_hash: String,
+        last_valid_cursor: Cursor,
+    ) -> Result<(), DBError>;
+}
+
+#[async_trait]
+impl HistoryLoader for DBLoader {
+    async fn undo(
+        &mut self,
+        output_module_hash: String,
+        last_valid_cursor: Cursor,
+    ) -> Result<(), DBError> {
+        // pending operations belong to blocks which are undone
+        self.reset()?;
+
+        let schema = self.get_schema().clone();
+        let module_name = self.get_output_module_name().clone();
+        let primary_keys = self.get_primary_keys();
+        let last_valid_block_num = last_valid_cursor.block.num;
+
+        self.connection()
+            .await?
+            .build_transaction()
+            .read_write()
+            .run::<_, DBError, _>(|conn| {
+                async move {
+                    revert_history(
+                        &schema,
+                        &primary_keys,
+                        &output_module_hash,
+                        last_valid_block_num,
+                        conn,
+                    )
+                    .await?;
+                    // update the cursors table
+                    Self::write_cursor(
+                        &schema,
+                        output_module_hash,
+                        module_name,
+                        last_valid_cursor,
+                        true,
+                        0,
+                        conn,
+                    )
+                    .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        self.reset_flush_state();
+
+        Ok(())
+    }
+}
+
+/// Reverts all changes recorded in the `history` table for output module hash
+/// `module_hash`, for blocks after block `last_valid_block_num`, most recent first, and
+/// removes them from the table. Tables are identified by their primary key column names,
+/// in `primary_keys`.
+pub(crate) async fn revert_history(
+    schema: &str,
+    primary_keys: &HashMap<String, Vec<String>>,
+    module_hash: &str,
+    last_valid_block_num: u64,
+    conn: &mut AsyncPgConnection,
+) -> Result<(), DBError> {
+    #[derive(QueryableByName)]
+    struct HistoryRow {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        id: i64,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        table_name: String,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        op_type: String,
+    }
+
+    // revert the most recent changes first
+    let rows = sql_query(format!(
+        "SELECT id, table_name, op_type FROM {}.{} WHERE block_num > $1 AND {} ORDER BY id DESC",
+        schema,
+        HISTORY_TABLE,
+        module_hash_condition(2)
+    ))
+    .bind::<diesel::sql_types::BigInt, _>(last_valid_block_num as i64)
+    .bind::<diesel::sql_types::Text, _>(module_hash)
+    .load::<HistoryRow>(conn)
+    .await?;
+
+    for row in rows {
+        let primary_key = primary_keys
+            .get(&row.table_name)
+            .ok_or(DBError::TableNotFound(row.table_name.clone()))?;
+        let queries = undo_queries(schema, &row.table_name, primary_key, &row.op_type, row.id)?;
+        for query in queries {
+            DBLoader::execute_query(query, conn).await?;
+        }
+    }
+
+    sql_query(format!(
+        "DELETE FROM {}.{} WHERE block_num > $1 AND {}",
+        schema,
+        HISTORY_TABLE,
+        module_hash_condition(2)
+    ))
+    .bind::<diesel::sql_types::BigInt, _>(last_valid_block_num as i64)
+    .bind::<diesel::sql_types::Text, _>(module_hash)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Condition selecting the `history` rows recorded for the output module hash bound as
+/// `$param`. Rows recorded before module hashes were, i.e. with a `NULL` hash, belong
+/// to any module, as a schema was then only written by one.
+fn module_hash_condition(param: usize) -> String {
+    format!("(module_hash = ${} OR module_hash IS NULL)", param)
+}
+
+/// Code of an operation type, as stored in the `history` table.
+fn op_type_code(op_type: &OperationType) -> &'static str {
+    match op_type {
+        OperationType::Insert => "I",
+        OperationType::Update => "U",
+        OperationType::Delete => "D",
+    }
+}
+
+/// Builds the query recording the row targeted by `op` in the `history` table, as a
+/// change of type `op_type`, for block `block_num` and output module hash `module_hash`.
+/// For updates and deletes, it is to be executed before the operation, so that the
+/// previous row is recorded. For inserts, it is to be executed after the operation, so
+/// that the inserted primary key is recorded.
+pub(crate) fn record_query(
+    op: &Operation,
+    op_type: &OperationType,
+    block_num: u64,
+    module_hash: &str,
+) -> Query {
+    let conditions = op
+        .primary_key()
+        .iter()
+        .enumerate()
+        .map(|(i, (k, _))| format!("t.{} = ${}", k, i + 1))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let mut binds = op
+        .primary_key()
+        .iter()
+        .map(|(_, v)| v.clone())
+        .collect::<Vec<_>>();
+    binds.push(ColumnValue::BigInt(BigInt::set_inner(block_num as i64)));
+    binds.push(ColumnValue::Text(Text::set_inner(module_hash.to_string())));
+
+    Query {
+        sql: format!(
+            "INSERT INTO {}.{} (block_num, module_hash, table_name, op_type, row_data) SELECT ${}, ${}, '{}', '{}', to_jsonb(t) FROM {}.{} t WHERE {}",
+            op.schema_name(),
+            HISTORY_TABLE,
+            binds.len() - 1,
+            binds.len(),
+            op.table_name(),
+            op_type_code(op_type),
+            op.schema_name(),
+            op.table_name(),
+            conditions
+        ),
+        binds,
+    }
+}
+
+/// Wraps the queries flushing `entries` with the queries recording their changes
+/// in the `history` table, for block `block_num` and output module hash `module_hash`.
+/// An insert replacing a row is recorded as the delete of the row, followed by the
+/// insert, so that both are reverted. History of the module older than `depth` blocks
+/// is pruned.
+pub(crate) fn with_history(
+    entries: &HashMap<String, HashMap<String, Operation>>,
+    queries: Vec<Query>,
+    schema: &str,
+    module_hash: &str,
+    block_num: u64,
+    depth: u64,
+) -> Vec<Query> {
+    let ops = entries.values().flat_map(|ops| ops.values());
+    let (inserts, others): (Vec<_>, Vec<_>) =
+        ops.partition(|op| *op.op_type() == OperationType::Insert);
+
+    let replaced = inserts.iter().filter(|op| op.replace());
+
+    others
+        .iter()
+        .map(|op| record_query(op, op.op_type(), block_num, module_hash))
+        .chain(replaced.map(|op| record_query(op, &OperationType::Delete, block_num, module_hash)))
+        .chain(queries)
+        .chain(
+            inserts
+                .iter()
+                .map(|op| record_query(op, op.op_type(), block_num, module_hash)),
+        )
+        .chain(std::iter::once(Query {
+            sql: format!(
+                "DELETE FROM {}.{} WHERE block_num <= $1 AND {}",
+                schema,
+                HISTORY_TABLE,
+                module_hash_condition(2)
+            ),
+            binds: vec![
+                ColumnValue::BigInt(BigInt::set_inner(block_num.saturating_sub(depth) as i64)),
+                ColumnValue::Text(Text::set_inner(module_hash.to_string())),
+            ],
+        }))
+        .collect()
+}
+
+/// Builds the queries reverting the change recorded in the `history` table row `id`,
+/// on table `table_name` with primary key columns `primary_key`. Inserted rows are
+/// deleted, while updated and deleted rows are restored to their recorded value.
+pub(crate) fn undo_queries(
+    schema: &str,
+    table_name: &str,
+    primary_key: &[String],
+    op_type: &str,
+    id: i64,
+) -> Result<Vec<Query>, DBError> {
+    let conditions = primary_key
+        .iter()
+        .map(|k| format!("t.{} = r.{}", k, k))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let delete = Query {
+        sql: format!(
+            "DELETE FROM {}.{} t USING {}.{} h, jsonb_populate_record(NULL::{}.{}, h.row_data) r WHERE h.id = $1 AND {}",
+            schema, table_name, schema, HISTORY_TABLE, schema, table_name, conditions
+        ),
+        binds: vec![ColumnValue::BigInt(BigInt::set_inner(id))],
+    };
+    let restore = Query {
+        sql: format!(
+            "INSERT INTO {}.{} SELECT r.* FROM {}.{} h, jsonb_populate_record(NULL::{}.{}, h.row_data) r WHERE h.id = $1",
+            schema, table_name, schema, HISTORY_TABLE, schema, table_name
+        ),
+        binds: vec![ColumnValue::BigInt(BigInt::set_inner(id))],
+    };
+
+    match op_type {
+        "I" => Ok(vec![delete]),
+        "U" => Ok(vec![delete, restore]),
+        "D" => Ok(vec![restore]),
+        _ => Err(DBError::InvalidHistoryOperation(op_type.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_record_query() {
+        let operation = Operation::new(
+            "my_scheme".to_string(),
+            "my_table".to_string(),
+            OperationType::Update,
+            vec![
+                (
+                    "uri".to_string(),
+                    ColumnValue::Text(Text::set_inner("ipfs://Qm".to_string())),
+                ),
+                (
+                    "app_id".to_string(),
+                    ColumnValue::Text(Text::set_inner("lenster".to_string())),
+                ),
+            ],
+            HashMap::new(),
+        );
+
+        let query = record_query(&operation, operation.op_type(), 10, "abc");
+        assert_eq!(
+            query.sql,
+            "INSERT INTO my_scheme.history (block_num, module_hash, table_name, op_type, row_data) SELECT $3, $4, 'my_table', 'U', to_jsonb(t) FROM my_scheme.my_table t WHERE t.uri = $1 AND t.app_id = $2"
+        );
+        assert_eq!(
+            query.binds,
+            vec![
+                ColumnValue::Text(Text::set_inner("ipfs://Qm".to_string())),
+                ColumnValue::Text(Text::set_inner("lenster".to_string())),
+                ColumnValue::BigInt(BigInt::set_inner(10)),
+                ColumnValue::Text(Text::set_inner("abc".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_works_with_history() {
+        let operation = Operation::new(
+            "s".to_string(),
+            "t".to_string(),
+            OperationType::Delete,
+            vec![(
+                "id".to_string(),
+                ColumnValue::Text(Text::set_inner("1".to_string())),
+            )],
+            HashMap::new(),
+        );
+        let entries = HashMap::from([(
+            "t".to_string(),
+            HashMap::from([("1".to_string(), operation)]),
+        )]);
+
+        let queries = with_history(&entries, Vec::new(), "s", "abc", 1500, 1000);
+        assert_eq!(
+            queries.iter().map(|q| q.sql.as_str()).collect::<Vec<_>>(),
+            vec![
+                "INSERT INTO s.history (block_num, module_hash, table_name, op_type, row_data) SELECT $2, $3, 't', 'D', to_jsonb(t) FROM s.t t WHERE t.id = $1",
+                "DELETE FROM s.history WHERE block_num <= $1 AND (module_hash = $2 OR module_hash IS NULL)",
+            ]
+        );
+        assert_eq!(
+            queries[1].binds,
+            vec![
+                ColumnValue::BigInt(BigInt::set_inner(500)),
+                ColumnValue::Text(Text::set_inner("abc".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_works_undo_queries() {
+        let primary_key = vec!["id".to_string()];
+        let delete = "DELETE FROM s.t t USING s.history h, jsonb_populate_record(NULL::s.t, h.row_data) r WHERE h.id = $1 AND t.id = r.id";
+        let restore = "INSERT INTO s.t SELECT r.* FROM s.history h, jsonb_populate_record(NULL::s.t, h.row_data) r WHERE h.id = $1";
+
+        let sql = |op_type| {
+            undo_queries("s", "t", &primary_key, op_type, 1)
+                .unwrap()
+                .into_iter()
+                .map(|q| q.sql)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sql("I"), vec![delete]);
+        assert_eq!(sql("U"), vec![delete, restore]);
+        assert_eq!(sql("D"), vec![restore]);
+        assert!(undo_queries("s", "t", &primary_key, "X", 1).is_err());
+    }
+}
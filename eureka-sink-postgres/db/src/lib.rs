@@ -1,7 +1,11 @@
 pub mod cursor;
 pub mod db_loader;
-mod error;
+pub mod error;
 pub mod flush;
+pub mod history;
+pub mod metadata;
+pub mod migrations;
 pub mod operation;
 pub mod ops;
 pub mod sql_types;
+pub mod tables;
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use diesel::{sql_query, QueryableByName};
+use diesel_async::RunQueryDsl;
+
+use crate::{db_loader::DBLoader, error::DBError};
+
+/// Name of the table recording how the sink wrote the current schema, as key and value
+/// pairs, e.g. the scheme from which primary keys are derived.
+pub const METADATA_TABLE: &str = "sink_metadata";
+
+/// Trait to read and write the `sink_metadata` table, set up with
+/// [`DBLoader::set_up_metadata_table`].
+#[async_trait]
+pub trait MetadataLoader {
+    /// Gets the value recorded for `key`, if any.
+    async fn get_metadata(&self, key: &str) -> Result<Option<String>, DBError>;
+    /// Records `value` for `key`, replacing any previous value.
+    async fn set_metadata(&self, key: &str, value: &str) -> Result<(), DBError>;
+}
+
+#[async_trait]
+impl MetadataLoader for DBLoader {
+    async fn get_metadata(&self, key: &str) -> Result<Option<String>, DBError> {
+        #[derive(QueryableByName)]
+        struct MetadataRow {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            value: String,
+        }
+
+        let rows = sql_query(format!(
+            "SELECT value FROM {}.{} WHERE key = $1",
+            self.get_schema(),
+            METADATA_TABLE
+        ))
+        .bind::<diesel::sql_types::Text, _>(key)
+        .load::<MetadataRow>(&mut self.connection().await?)
+        .await?;
+
+        Ok(rows.into_iter().next().map(|row| row.value))
+    }
+
+    async fn set_metadata(&self, key: &str, value: &str) -> Result<(), DBError> {
+        sql_query(format!(
+            "INSERT INTO {}.{} (key, value) VALUES ($1, $2) ON CONFLICT (key) DO UPDATE SET value = $2",
+            self.get_schema(),
+            METADATA_TABLE
+        ))
+        .bind::<diesel::sql_types::Text, _>(key)
+        .bind::<diesel::sql_types::Text, _>(value)
+        .execute(&mut self.connection().await?)
+        .await?;
+
+        Ok(())
+    }
+}
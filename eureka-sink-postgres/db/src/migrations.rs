@@ -0,0 +1,314 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use async_trait::async_trait;
+use diesel::{sql_query, QueryableByName};
+use diesel_async::{scoped_futures::ScopedFutureExt, RunQueryDsl, SimpleAsyncConnection};
+use sha2::{Digest, Sha256};
+
+use crate::{db_loader::DBLoader, error::DBError};
+
+/// Name of the table recording the applied migrations, in the current schema.
+pub const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+#[derive(Debug, Clone, PartialEq)]
+/// A schema migration, read from a `<version>_<name>.sql` file.
+pub struct Migration {
+    /// Version of the migration, migrations are applied in version order.
+    pub version: i64,
+    /// Name of the migration.
+    pub name: String,
+    /// SQL statements of the migration.
+    pub sql: String,
+    /// Hex encoded SHA-256 digest of the SQL statements, to detect
+    /// migrations which were edited after being applied.
+    pub checksum: String,
+}
+
+impl Migration {
+    pub fn new(version: i64, name: String, sql: String) -> Self {
+        let checksum = hex::encode(Sha256::digest(sql.as_bytes()));
+        Self {
+            version,
+            name,
+            sql,
+            checksum,
+        }
+    }
+
+    /// Reads all the migrations of a directory, ordered by version. Migration files
+    /// are named `<version>_<name>.sql`, e.g. `0001_create_posts.sql`, while other
+    /// files are ignored.
+    pub fn read_dir(dir: &Path) -> Result<Vec<Self>, DBError> {
+        let mut migrations = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("sql") {
+                continue;
+            }
+
+            let file_name = path.display().to_string();
+            let (version, name) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.split_once('_'))
+                .ok_or(DBError::InvalidMigrationName(file_name.clone()))?;
+            let version = version
+                .parse()
+                .map_err(|_| DBError::InvalidMigrationName(file_name.clone()))?;
+            let sql = std::fs::read_to_string(&path)?;
+
+            migrations.push(Self::new(version, name.to_string(), sql));
+        }
+
+        migrations.sort_by_key(|m| m.version);
+        if let Some(w) = migrations.windows(2).find(|w| w[0].version == w[1].version) {
+            return Err(DBError::DuplicateMigrationVersion(w[0].version));
+        }
+
+        Ok(migrations)
+    }
+}
+
+/// A migration, as recorded in the `schema_migrations` table.
+#[derive(QueryableByName, Debug)]
+struct AppliedMigration {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    checksum: String,
+}
+
+/// Trait to apply schema migrations through a [`DBLoader`], keeping track of the
+/// applied ones in the `schema_migrations` table.
+#[async_trait]
+pub trait MigrationLoader {
+    /// Checks `migrations` against the applied ones, and returns those which are
+    /// still to be applied, in version order.
+    async fn pending_migrations(&self, migrations: &[Migration])
+        -> Result<Vec<Migration>, DBError>;
+    /// Applies the pending `migrations`, each one in its own transaction,
+    /// and returns them. Unqualified names refer to the current schema.
+    async fn migrate(&self, migrations: &[Migration]) -> Result<Vec<Migration>, DBError>;
+}
+
+#[async_trait]
+impl MigrationLoader for DBLoader {
+    async fn pending_migrations(
+        &self,
+        migrations: &[Migration],
+    ) -> Result<Vec<Migration>, DBError> {
+        self.set_up_migrations_table().await?;
+
+        let applied = sql_query(format!(
+            "SELECT version, checksum FROM {}.{} ORDER BY version",
+            self.get_schema(),
+            MIGRATIONS_TABLE
+        ))
+        .load::<AppliedMigration>(&mut self.connection().await?)
+        .await?;
+
+        pending_migrations(migrations, &applied)
+    }
+
+    async fn migrate(&self, migrations: &[Migration]) -> Result<Vec<Migration>, DBError> {
+        let pending = self.pending_migrations(migrations).await?;
+
+        for migration in pending.iter() {
+            let schema = self.get_schema().clone();
+            let migration = migration.clone();
+            self.connection()
+                .await?
+                .build_transaction()
+                .read_write()
+                .run::<_, DBError, _>(|conn| {
+                    async move {
+                        conn.batch_execute(&format!("SET LOCAL search_path TO {}, public", schema))
+                            .await?;
+                        conn.batch_execute(&migration.sql).await.map_err(|e| {
+                            DBError::FailedToExecuteQuery {
+                                query: format!(
+                                    "migration {}_{}",
+                                    migration.version, migration.name
+                                ),
+                                error: e.to_string(),
+                            }
+                        })?;
+
+                        // record the migration, along with its checksum
+                        sql_query(format!(
+                            "INSERT INTO {}.{} (version, name, checksum) VALUES ($1, $2, $3)",
+                            schema, MIGRATIONS_TABLE
+                        ))
+                        .bind::<diesel::sql_types::BigInt, _>(migration.version)
+                        .bind::<diesel::sql_types::Text, _>(migration.name)
+                        .bind::<diesel::sql_types::Text, _>(migration.checksum)
+                        .execute(conn)
+                        .await?;
+
+                        Ok(())
+                    }
+                    .scope_boxed()
+                })
+                .await?;
+        }
+
+        Ok(pending)
+    }
+}
+
+/// Checks `migrations` against the `applied` ones, and returns those which are still
+/// to be applied, in version order. Applied migrations must not have been edited nor
+/// removed, and pending migrations must be newer than all applied ones.
+fn pending_migrations(
+    migrations: &[Migration],
+    applied: &[AppliedMigration],
+) -> Result<Vec<Migration>, DBError> {
+    let migrations_by_version = migrations
+        .iter()
+        .map(|m| (m.version, m))
+        .collect::<HashMap<_, _>>();
+    for applied_migration in applied {
+        let migration = migrations_by_version
+            .get(&applied_migration.version)
+            .ok_or(DBError::MigrationNotFound(applied_migration.version))?;
+        if migration.checksum != applied_migration.checksum {
+            return Err(DBError::MigrationChecksumMismatch {
+                version: migration.version,
+                name: migration.name.clone(),
+            });
+        }
+    }
+
+    let applied_versions = applied.iter().map(|m| m.version).collect::<HashSet<_>>();
+    let mut pending = migrations
+        .iter()
+        .filter(|m| !applied_versions.contains(&m.version))
+        .cloned()
+        .collect::<Vec<_>>();
+    pending.sort_by_key(|m| m.version);
+
+    let latest = applied_versions.iter().max();
+    let oldest_pending = pending.iter().map(|m| m.version).min();
+    if let (Some(latest), Some(version)) = (latest, oldest_pending) {
+        if version < *latest {
+            return Err(DBError::MigrationOutOfOrder {
+                version,
+                latest: *latest,
+            });
+        }
+    }
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: migration.checksum.clone(),
+        }
+    }
+
+    #[test]
+    fn it_works_read_dir() {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("0002_add_posts_app.sql"),
+            "ALTER TABLE posts ADD app TEXT;",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("0001_create_posts.sql"),
+            "CREATE TABLE posts (id TEXT);",
+        )
+        .unwrap();
+        std::fs::write(dir.join("README.md"), "migrations").unwrap();
+
+        let migrations = Migration::read_dir(&dir).unwrap();
+        assert_eq!(
+            migrations,
+            vec![
+                Migration::new(
+                    1,
+                    "create_posts".to_string(),
+                    "CREATE TABLE posts (id TEXT);".to_string()
+                ),
+                Migration::new(
+                    2,
+                    "add_posts_app".to_string(),
+                    "ALTER TABLE posts ADD app TEXT;".to_string()
+                ),
+            ]
+        );
+
+        std::fs::write(dir.join("2_duplicate.sql"), "").unwrap();
+        assert!(matches!(
+            Migration::read_dir(&dir),
+            Err(DBError::DuplicateMigrationVersion(2))
+        ));
+        std::fs::write(dir.join("latest.sql"), "").unwrap();
+        assert!(matches!(
+            Migration::read_dir(&dir),
+            Err(DBError::InvalidMigrationName(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_works_pending_migrations() {
+        let first = Migration::new(
+            1,
+            "create".to_string(),
+            "CREATE TABLE t (id TEXT);".to_string(),
+        );
+        let second = Migration::new(
+            2,
+            "alter".to_string(),
+            "ALTER TABLE t ADD v TEXT;".to_string(),
+        );
+        let migrations = vec![first.clone(), second.clone()];
+
+        assert_eq!(pending_migrations(&migrations, &[]).unwrap(), migrations);
+        assert_eq!(
+            pending_migrations(&migrations, &[applied(&first)]).unwrap(),
+            vec![second.clone()]
+        );
+        assert!(
+            pending_migrations(&migrations, &[applied(&first), applied(&second)])
+                .unwrap()
+                .is_empty()
+        );
+
+        // edited after being applied
+        let edited = Migration::new(
+            1,
+            "create".to_string(),
+            "CREATE TABLE t (id INT);".to_string(),
+        );
+        assert!(matches!(
+            pending_migrations(&[edited, second.clone()], &[applied(&first)]),
+            Err(DBError::MigrationChecksumMismatch { version: 1, .. })
+        ));
+        // removed after being applied
+        assert!(matches!(
+            pending_migrations(std::slice::from_ref(&second), &[applied(&first)]),
+            Err(DBError::MigrationNotFound(1))
+        ));
+        // older than the latest applied one
+        assert!(matches!(
+            pending_migrations(&migrations, &[applied(&second)]),
+            Err(DBError::MigrationOutOfOrder {
+                version: 1,
+                latest: 2
+            })
+        ));
+    }
+}
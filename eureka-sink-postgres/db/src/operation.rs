@@ -10,6 +10,14 @@ pub enum OperationType {
     Delete,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// A parameterized SQL query, together with the values to be bound
+/// to its `$n` placeholders, in order.
+pub struct Query {
+    pub sql: String,
+    pub binds: Vec<ColumnValue>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 /// [`Operation`] provides interface to apply changes to the DB, via a [`DBLoader`]
@@ -19,15 +27,17 @@ pub struct Operation {
     schema_name: String,
     /// The table name.
     table_name: String,
-    /// The primary key column name, for the current table.
-    primary_key_column_name: String,
     /// Checks which db operation is, either insert, update or delete.
     op_type: OperationType,
-    /// Primary key value, with type already parsed in.
-    primary_key: ColumnValue,
+    /// Primary key column names and their values, with type already parsed in,
+    /// in the order of the table primary key.
+    primary_key: Vec<(String, ColumnValue)>,
     /// The data to be applied on the operation. Consists of a mapping from
     /// column name to its value, with type parsed in.
     data: HashMap<String, ColumnValue>, // mapping data row from columns -> field
+    /// Whether an insert replaces a row whose deletion was scheduled earlier, within
+    /// the same flush, in which case the row is deleted before being inserted again.
+    replace: bool,
 }
 
 #[allow(dead_code)]
@@ -35,71 +45,157 @@ impl Operation {
     pub fn new(
         schema_name: String,
         table_name: String,
-        primary_key_column_name: String,
         op_type: OperationType,
-        primary_key: ColumnValue,
+        primary_key: Vec<(String, ColumnValue)>,
         data: HashMap<String, ColumnValue>,
     ) -> Self {
         Self {
             schema_name,
             table_name,
-            primary_key_column_name,
             op_type,
             primary_key,
             data,
+            replace: false,
         }
     }
 
     /// Builds a query to be executed by a [`DBLoader`] instance, which depends
     /// on the operation type and the provided data, as well as the primary key value.
-    pub fn build_query(&self) -> String {
-        let query = match self.op_type {
-            OperationType::Delete => {
-                format!(
-                    "DELETE FROM {}.{} WHERE {} = {}",
-                    self.schema_name,
-                    self.table_name,
-                    self.primary_key_column_name,
-                    self.primary_key.to_string()
-                )
-            }
+    /// Values are never spliced into the SQL string, instead they are referenced by
+    /// `$n` placeholders and returned as an ordered list of binds.
+    pub fn build_query(&self) -> Query {
+        // sort columns by name, so that the generated query is deterministic
+        let mut columns = self.data.iter().collect::<Vec<_>>();
+        columns.sort_by_key(|(k, _)| *k);
+
+        match self.op_type {
+            OperationType::Delete => self.build_delete_query(),
             OperationType::Insert => {
-                let mut keys = "".to_string();
-                let mut values = "".to_string();
-
-                self.data.iter().for_each(|(k, v)| {
-                    keys.push_str(format!(",{}", k).as_str());
-                    values.push_str(format!(",{}", v.to_string()).as_str());
-                });
-                // remove extra initial ','
-                keys.remove(0); // ,col1,col2,col3,col4 -> col1,col2,col3,col4
-                values.remove(0); // ,val1,val2,val3,val4 -> val1,val2,val3,val4
-
-                format!(
-                    "INSERT INTO {}.{} ({}) VALUES ({})",
-                    self.schema_name, self.table_name, keys, values
-                )
+                let keys = columns
+                    .iter()
+                    .map(|(k, _)| k.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let placeholders = (1..=columns.len())
+                    .map(|i| format!("${}", i))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                Query {
+                    sql: format!(
+                        "INSERT INTO {}.{} ({}) VALUES ({})",
+                        self.schema_name, self.table_name, keys, placeholders
+                    ),
+                    binds: columns.into_iter().map(|(_, v)| v.clone()).collect(),
+                }
             }
             OperationType::Update => {
-                let mut updates = "".to_string();
-
-                self.data.iter().for_each(|(k, v)| {
-                    updates.push_str(format!(",{}={}", k, v.to_string()).as_str())
-                });
-                // remove extra initial ','
-                updates.remove(0); //,col1=val1,col2=val2,col3=val3 -> col1=val1,col2=val2,col3=val3
-                format!(
-                    "UPDATE {}.{} SET {} WHERE {}={}",
-                    self.schema_name,
-                    self.table_name,
-                    updates,
-                    self.primary_key_column_name,
-                    self.primary_key.to_string()
-                )
+                let updates = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (k, _))| format!("{}=${}", k, i + 1))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let conditions = self
+                    .primary_key
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (k, _))| format!("{}=${}", k, columns.len() + i + 1))
+                    .collect::<Vec<_>>()
+                    .join(" AND ");
+
+                let binds = columns
+                    .into_iter()
+                    .map(|(_, v)| v.clone())
+                    .chain(self.primary_key.iter().map(|(_, v)| v.clone()))
+                    .collect::<Vec<_>>();
+
+                Query {
+                    sql: format!(
+                        "UPDATE {}.{} SET {} WHERE {}",
+                        self.schema_name, self.table_name, updates, conditions
+                    ),
+                    binds,
+                }
             }
-        };
+        }
+    }
 
-        query
+    /// Builds the queries to be executed for the operation, that is the query of
+    /// [`Operation::build_query`], preceded by the deletion of the replaced row, if any.
+    pub fn build_queries(&self) -> Vec<Query> {
+        if self.replace {
+            vec![self.build_delete_query(), self.build_query()]
+        } else {
+            vec![self.build_query()]
+        }
+    }
+
+    /// Builds a query deleting the row targeted by the operation, whatever its type.
+    pub fn build_delete_query(&self) -> Query {
+        let conditions = self
+            .primary_key
+            .iter()
+            .enumerate()
+            .map(|(i, (k, _))| format!("{} = ${}", k, i + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        Query {
+            sql: format!(
+                "DELETE FROM {}.{} WHERE {}",
+                self.schema_name, self.table_name, conditions,
+            ),
+            binds: self.primary_key.iter().map(|(_, v)| v.clone()).collect(),
+        }
+    }
+
+    /// Builds a single multi-row `INSERT` query for a batch of insert operations.
+    /// All operations are expected to target the same table, with the same set of columns.
+    pub fn build_batch_insert_query(operations: &[&Operation]) -> Query {
+        let first = operations
+            .first()
+            .expect("Batch of insert operations should not be empty");
+        let mut columns = first.data.keys().collect::<Vec<_>>();
+        columns.sort();
+
+        let keys = columns
+            .iter()
+            .map(|k| k.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut rows = Vec::with_capacity(operations.len());
+        let mut binds = Vec::with_capacity(operations.len() * columns.len());
+        for op in operations {
+            let placeholders = columns
+                .iter()
+                .map(|k| {
+                    binds.push(op.data[*k].clone());
+                    format!("${}", binds.len())
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            rows.push(format!("({})", placeholders));
+        }
+
+        Query {
+            sql: format!(
+                "INSERT INTO {}.{} ({}) VALUES {}",
+                first.schema_name,
+                first.table_name,
+                keys,
+                rows.join(",")
+            ),
+            binds,
+        }
+    }
+
+    /// Gets the sorted column names of the operation data.
+    pub fn column_names(&self) -> Vec<String> {
+        let mut columns = self.data.keys().cloned().collect::<Vec<_>>();
+        columns.sort();
+        columns
     }
 
     pub fn schema_name(&self) -> &str {
@@ -110,11 +206,7 @@ impl Operation {
         &self.table_name
     }
 
-    pub fn primary_key_column_name(&self) -> &str {
-        &self.primary_key_column_name
-    }
-
-    pub fn primary_key(&self) -> &ColumnValue {
+    pub fn primary_key(&self) -> &Vec<(String, ColumnValue)> {
         &self.primary_key
     }
 
@@ -125,6 +217,21 @@ impl Operation {
     pub fn data(&self) -> &HashMap<String, ColumnValue> {
         &self.data
     }
+
+    pub fn replace(&self) -> bool {
+        self.replace
+    }
+
+    /// Sets whether the insert replaces a row whose deletion was scheduled earlier.
+    pub(crate) fn set_replace(&mut self, replace: bool) {
+        self.replace = replace;
+    }
+
+    /// Merges new column values into the operation data, overriding
+    /// previously scheduled values for the same columns.
+    pub(crate) fn merge_data(&mut self, data: HashMap<String, ColumnValue>) {
+        self.data.extend(data);
+    }
 }
 
 #[cfg(test)]
@@ -157,17 +264,25 @@ mod tests {
         let operation = Operation::new(
             "my_scheme".to_string(),
             "my_table".to_string(),
-            "my_primary_key_column_name".to_string(),
             OperationType::Delete,
-            ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            vec![(
+                "my_primary_key_column_name".to_string(),
+                ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            )],
             data,
         );
 
         let query = operation.build_query();
 
         assert_eq!(
-            query,
-            "DELETE FROM my_scheme.my_table WHERE my_primary_key_column_name = 'field_to_delete'"
+            query.sql,
+            "DELETE FROM my_scheme.my_table WHERE my_primary_key_column_name = $1"
+        );
+        assert_eq!(
+            query.binds,
+            vec![ColumnValue::Text(Text::set_inner(
+                "field_to_delete".to_string()
+            ))]
         );
     }
 
@@ -193,27 +308,31 @@ mod tests {
         let operation = Operation::new(
             "my_scheme".to_string(),
             "my_table".to_string(),
-            "my_primary_key_column_name".to_string(),
             OperationType::Insert,
-            ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            vec![(
+                "my_primary_key_column_name".to_string(),
+                ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            )],
             data,
         );
 
         let query = operation.build_query();
 
-        // hash maps don't order store their (k, v) pairs
-        // for this reason, it is possible to have six, and only six,
-        // different queries
-        let possible_queries = [
-            "INSERT INTO my_scheme.my_table (col1,col2,col3) VALUES (10,'2023-03-01',[0, 1, 2])",
-            "INSERT INTO my_scheme.my_table (col2,col1,col3) VALUES ('2023-03-01',10,[0, 1, 2])",
-            "INSERT INTO my_scheme.my_table (col2,col3,col1) VALUES ('2023-03-01',[0, 1, 2],10)",
-            "INSERT INTO my_scheme.my_table (col3,col1,col2) VALUES ([0, 1, 2],10,'2023-03-01')",
-            "INSERT INTO my_scheme.my_table (col3,col2,col1) VALUES ([0, 1, 2],'2023-03-01',10)",
-            "INSERT INTO my_scheme.my_table (col1,col3,col2) VALUES (10,[0, 1, 2],'2023-03-01')",
-        ];
-
-        assert!(possible_queries.contains(&query.as_str()));
+        // columns are sorted by name, so the query is deterministic
+        assert_eq!(
+            query.sql,
+            "INSERT INTO my_scheme.my_table (col1,col2,col3) VALUES ($1,$2,$3)"
+        );
+        assert_eq!(
+            query.binds,
+            vec![
+                ColumnValue::Integer(Integer::set_inner(10)),
+                ColumnValue::Date(Date::set_inner(
+                    NaiveDate::from_ymd_opt(2023, 3, 1).unwrap()
+                )),
+                ColumnValue::Binary(Binary::set_inner(vec![0u8, 1, 2])),
+            ]
+        );
     }
 
     #[test]
@@ -238,26 +357,215 @@ mod tests {
         let operation = Operation::new(
             "my_scheme".to_string(),
             "my_table".to_string(),
-            "my_primary_key_column_name".to_string(),
             OperationType::Update,
-            ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            vec![(
+                "my_primary_key_column_name".to_string(),
+                ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            )],
+            data,
+        );
+
+        let query = operation.build_query();
+
+        // columns are sorted by name, so the query is deterministic
+        assert_eq!(
+            query.sql,
+            "UPDATE my_scheme.my_table SET col1=$1,col2=$2,col3=$3 WHERE my_primary_key_column_name=$4"
+        );
+        assert_eq!(
+            query.binds,
+            vec![
+                ColumnValue::Integer(Integer::set_inner(10_i32)),
+                ColumnValue::Date(Date::set_inner(
+                    NaiveDate::from_ymd_opt(2023, 3, 1).unwrap()
+                )),
+                ColumnValue::Binary(Binary::set_inner(vec![0u8, 1, 2])),
+                ColumnValue::Text(Text::set_inner("field_to_delete".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_works_build_insert_into_query_with_quotes() {
+        let data = HashMap::from([(
+            "content".to_string(),
+            ColumnValue::Text(Text::set_inner(
+                "it's'); DROP TABLE my_table; --".to_string(),
+            )),
+        )]);
+
+        let operation = Operation::new(
+            "my_scheme".to_string(),
+            "my_table".to_string(),
+            OperationType::Insert,
+            vec![(
+                "my_primary_key_column_name".to_string(),
+                ColumnValue::Text(Text::set_inner("field_to_insert".to_string())),
+            )],
             data,
         );
 
         let query = operation.build_query();
 
-        // hash maps don't order store their (k, v) pairs
-        // for this reason, it is possible to have six, and only six,
-        // different queries
-        let possible_queries = [
-            "UPDATE my_scheme.my_table SET col1=10,col2='2023-03-01',col3=[0, 1, 2] WHERE my_primary_key_column_name='field_to_delete'",
-            "UPDATE my_scheme.my_table SET col3=[0, 1, 2],col2='2023-03-01',col1=10 WHERE my_primary_key_column_name='field_to_delete'",
-            "UPDATE my_scheme.my_table SET col3=[0, 1, 2],col1=10,col2='2023-03-01' WHERE my_primary_key_column_name='field_to_delete'",
-            "UPDATE my_scheme.my_table SET col2='2023-03-01',col1=10,col3=[0, 1, 2] WHERE my_primary_key_column_name='field_to_delete'",
-            "UPDATE my_scheme.my_table SET col2='2023-03-01',col3=[0, 1, 2],col1=10 WHERE my_primary_key_column_name='field_to_delete'",
-            "UPDATE my_scheme.my_table SET col1=10,col3=[0, 1, 2],col2='2023-03-01' WHERE my_primary_key_column_name='field_to_delete'"
+        // values never end up in the SQL string
+        assert_eq!(
+            query.sql,
+            "INSERT INTO my_scheme.my_table (content) VALUES ($1)"
+        );
+        assert_eq!(
+            query.binds,
+            vec![ColumnValue::Text(Text::set_inner(
+                "it's'); DROP TABLE my_table; --".to_string()
+            ))]
+        );
+    }
+
+    #[test]
+    fn it_works_build_queries_with_composite_primary_key() {
+        let data = HashMap::from([(
+            "content".to_string(),
+            ColumnValue::Text(Text::set_inner("content".to_string())),
+        )]);
+        let primary_key = vec![
+            (
+                "uri".to_string(),
+                ColumnValue::Text(Text::set_inner("ipfs://Qm".to_string())),
+            ),
+            (
+                "app_id".to_string(),
+                ColumnValue::Text(Text::set_inner("lenster".to_string())),
+            ),
         ];
 
-        assert!(possible_queries.contains(&query.as_str()));
+        let delete = Operation::new(
+            "my_scheme".to_string(),
+            "my_table".to_string(),
+            OperationType::Delete,
+            primary_key.clone(),
+            data.clone(),
+        )
+        .build_query();
+        assert_eq!(
+            delete.sql,
+            "DELETE FROM my_scheme.my_table WHERE uri = $1 AND app_id = $2"
+        );
+        assert_eq!(
+            delete.binds,
+            vec![
+                ColumnValue::Text(Text::set_inner("ipfs://Qm".to_string())),
+                ColumnValue::Text(Text::set_inner("lenster".to_string())),
+            ]
+        );
+
+        let update = Operation::new(
+            "my_scheme".to_string(),
+            "my_table".to_string(),
+            OperationType::Update,
+            primary_key,
+            data,
+        )
+        .build_query();
+        assert_eq!(
+            update.sql,
+            "UPDATE my_scheme.my_table SET content=$1 WHERE uri=$2 AND app_id=$3"
+        );
+        assert_eq!(
+            update.binds,
+            vec![
+                ColumnValue::Text(Text::set_inner("content".to_string())),
+                ColumnValue::Text(Text::set_inner("ipfs://Qm".to_string())),
+                ColumnValue::Text(Text::set_inner("lenster".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_works_build_batch_insert_query() {
+        let operations = (0..3)
+            .map(|i| {
+                Operation::new(
+                    "my_scheme".to_string(),
+                    "my_table".to_string(),
+                    OperationType::Insert,
+                    vec![(
+                        "id".to_string(),
+                        ColumnValue::Text(Text::set_inner(format!("id{}", i))),
+                    )],
+                    HashMap::from([
+                        (
+                            "id".to_string(),
+                            ColumnValue::Text(Text::set_inner(format!("id{}", i))),
+                        ),
+                        (
+                            "col1".to_string(),
+                            ColumnValue::Integer(Integer::set_inner(i)),
+                        ),
+                    ]),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let query = Operation::build_batch_insert_query(&operations.iter().collect::<Vec<_>>());
+
+        assert_eq!(
+            query.sql,
+            "INSERT INTO my_scheme.my_table (col1,id) VALUES ($1,$2),($3,$4),($5,$6)"
+        );
+        assert_eq!(
+            query.binds,
+            vec![
+                ColumnValue::Integer(Integer::set_inner(0)),
+                ColumnValue::Text(Text::set_inner("id0".to_string())),
+                ColumnValue::Integer(Integer::set_inner(1)),
+                ColumnValue::Text(Text::set_inner("id1".to_string())),
+                ColumnValue::Integer(Integer::set_inner(2)),
+                ColumnValue::Text(Text::set_inner("id2".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_works_merge_data() {
+        let data = HashMap::from([
+            (
+                "col1".to_string(),
+                ColumnValue::Integer(Integer::set_inner(10)),
+            ),
+            (
+                "col2".to_string(),
+                ColumnValue::Text(Text::set_inner("old".to_string())),
+            ),
+        ]);
+
+        let mut operation = Operation::new(
+            "my_scheme".to_string(),
+            "my_table".to_string(),
+            OperationType::Insert,
+            vec![(
+                "my_primary_key_column_name".to_string(),
+                ColumnValue::Text(Text::set_inner("field_to_update".to_string())),
+            )],
+            data,
+        );
+
+        operation.merge_data(HashMap::from([(
+            "col2".to_string(),
+            ColumnValue::Text(Text::set_inner("new".to_string())),
+        )]));
+
+        assert_eq!(operation.op_type(), &OperationType::Insert);
+        assert_eq!(
+            operation.data(),
+            &HashMap::from([
+                (
+                    "col1".to_string(),
+                    ColumnValue::Integer(Integer::set_inner(10)),
+                ),
+                (
+                    "col2".to_string(),
+                    ColumnValue::Text(Text::set_inner("new".to_string())),
+                ),
+            ])
+        );
     }
 }
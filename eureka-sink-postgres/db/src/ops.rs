@@ -1,14 +1,19 @@
 use std::collections::HashMap;
 
 use crate::{
-    db_loader::DBLoader,
+    db_loader::{DBLoader, BLOCK_ID_COLUMN, BLOCK_NUM_COLUMN, BLOCK_TIMESTAMP_COLUMN},
     error::DBError,
     operation::{Operation, OperationType},
-    sql_types::ColumnValue,
+    sql_types::{BigInt, ColumnValue, Sql, Text, Timestamptz},
 };
 
+/// Separator between the values of a composite primary key, in a record id.
+pub const PRIMARY_KEY_SEPARATOR: char = ':';
+
 /// [`DBLoaderOperations`] introduces an interface to apply operations/changes
-/// to a DB, via a [`DBLoader`] instance.
+/// to a DB, via a [`DBLoader`] instance. The `primary_key` is the record id,
+/// which is mapped onto the primary key columns of the table (see
+/// [`DBLoader::get_primary_key_values`]).
 pub trait DBLoaderOperations {
     /// Inserts a new [`Delete`] operation in the [`DBLoader`]
     fn delete(
@@ -37,11 +42,28 @@ pub trait DBLoaderOperations {
 impl DBLoaderOperations for DBLoader {
     fn delete(
         &mut self,
-        _table_name: String,
-        _primary_key: String,
-        _data: HashMap<String, ColumnValue>,
+        table_name: String,
+        primary_key: String,
+        data: HashMap<String, ColumnValue>,
     ) -> Result<(), DBError> {
-        unimplemented!("To be implemented!")
+        // get primary key correct field types
+        let primary_key_vals = self.get_primary_key_values(&table_name, &primary_key)?;
+        // retrieve delete operation
+        let delete_op = self.new_operation(
+            table_name.clone(),
+            OperationType::Delete,
+            primary_key_vals,
+            data,
+        );
+
+        let ops = self.get_entries_mut().entry(table_name).or_default();
+        match schedule_delete(ops, primary_key, delete_op) {
+            EntriesChange::Added => self.increase_entries_count(),
+            EntriesChange::Removed => self.decrease_entries_count(),
+            EntriesChange::Unchanged => 0,
+        };
+
+        Ok(())
     }
 
     fn insert(
@@ -50,49 +72,179 @@ impl DBLoaderOperations for DBLoader {
         primary_key: String,
         data: HashMap<String, ColumnValue>,
     ) -> Result<(), DBError> {
-        // get primary key correct field type
-        let primary_key_colname = self
-            .get_primary_key_column_name(&table_name)
-            .expect(format!("Primary key not found for current table {}", &table_name).as_str());
-        let primary_key_val =
-            self.get_type(&table_name, &primary_key_colname, primary_key.clone())?;
-        // get data correct field type
-        let data = data
-            .iter()
-            .map(|(colname, val)| (colname.clone(), val.clone()))
-            .collect::<HashMap<String, ColumnValue>>();
+        // get primary key correct field types
+        let primary_key_vals = self.get_primary_key_values(&table_name, &primary_key)?;
+        // get data correct field type, the primary key columns
+        // are filled in from the primary key
+        let data = self.with_block_columns(&table_name, data);
+        let data = with_primary_key(self.coerce_data(&table_name, data)?, &primary_key_vals);
         // retrieve insert operation
-        let insert_op = self.new_insert_operation(table_name.clone(), primary_key_val, data);
+        let insert_op = self.new_operation(
+            table_name.clone(),
+            OperationType::Insert,
+            primary_key_vals,
+            data,
+        );
 
-        let entries = self.get_entries_mut();
         // if the current table_name does not exist in our entries hashmap, we simply
         // add to it, with empty operations
-        let ops = entries.entry(table_name.clone()).or_default();
-
-        // verify that the primary key hasn't been scheduled for a previous operation
-        // no update is performed on insert, append only
-        if ops.get(&primary_key).is_some() {
-            return Err(DBError::PrimaryKeyAlreadyScheduleForOperation {
-                table_name,
-                primary_key,
-            });
+        let ops = self
+            .get_entries_mut()
+            .entry(table_name.clone())
+            .or_default();
+        if schedule_insert(ops, table_name, primary_key, insert_op)? == EntriesChange::Added {
+            self.increase_entries_count();
         }
 
-        // we are guaranteed to insert the op in the hashmap, as we verified above that this didn't
-        // contain any already scheduled op for the given primary key and table
-        ops.entry(primary_key).or_insert(insert_op);
-        self.increase_entries_count();
-
         Ok(())
     }
 
     fn update(
         &mut self,
-        _table_name: String,
-        _primary_key: String,
-        _data: HashMap<String, ColumnValue>,
+        table_name: String,
+        primary_key: String,
+        data: HashMap<String, ColumnValue>,
     ) -> Result<(), DBError> {
-        unimplemented!("To be implemented!")
+        // get primary key correct field types
+        let primary_key_vals = self.get_primary_key_values(&table_name, &primary_key)?;
+        // get data correct field type, the primary key columns
+        // are only targeted, never updated
+        let data = self.with_block_columns(&table_name, data);
+        let mut data = self.coerce_data(&table_name, data)?;
+        data.retain(|colname, _| !primary_key_vals.iter().any(|(k, _)| k == colname));
+        // retrieve update operation
+        let update_op = self.new_operation(
+            table_name.clone(),
+            OperationType::Update,
+            primary_key_vals,
+            data,
+        );
+
+        let ops = self
+            .get_entries_mut()
+            .entry(table_name.clone())
+            .or_default();
+        if schedule_update(ops, table_name, primary_key, update_op)? == EntriesChange::Added {
+            self.increase_entries_count();
+        }
+
+        Ok(())
+    }
+}
+
+/// Change of the number of pending operations, once an operation is scheduled.
+#[derive(Debug, PartialEq)]
+enum EntriesChange {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+/// Sets the primary key columns of `data` to the values mapped from the record id, in
+/// `primary_key`, overriding any field named like a primary key column, so that later
+/// updates and deletes, which target the mapped values, find the row.
+fn with_primary_key(
+    mut data: HashMap<String, ColumnValue>,
+    primary_key: &[(String, ColumnValue)],
+) -> HashMap<String, ColumnValue> {
+    for (colname, val) in primary_key.iter() {
+        data.insert(colname.clone(), val.clone());
+    }
+    data
+}
+
+/// Schedules `delete_op` for `primary_key` among the pending operations `ops` of a table.
+fn schedule_delete(
+    ops: &mut HashMap<String, Operation>,
+    primary_key: String,
+    delete_op: Operation,
+) -> EntriesChange {
+    match ops
+        .get(&primary_key)
+        .map(|op| (op.op_type().clone(), op.replace()))
+    {
+        // the row replaced by the pending insert exists in the DB, so it
+        // must still be deleted
+        Some((OperationType::Insert, true)) => {
+            ops.insert(primary_key, delete_op);
+            EntriesChange::Unchanged
+        }
+        // the row was never written to the DB, so it suffices
+        // to cancel the pending insert
+        Some((OperationType::Insert, false)) => {
+            ops.remove(&primary_key);
+            EntriesChange::Removed
+        }
+        // the pending update is superseded by the delete
+        Some((OperationType::Update, _)) => {
+            ops.insert(primary_key, delete_op);
+            EntriesChange::Unchanged
+        }
+        // the row is already scheduled for deletion
+        Some((OperationType::Delete, _)) => EntriesChange::Unchanged,
+        None => {
+            ops.insert(primary_key, delete_op);
+            EntriesChange::Added
+        }
+    }
+}
+
+/// Schedules `insert_op` for `primary_key` among the pending operations `ops` of table
+/// `table_name`. No update is performed on insert, as inserts are append only, except
+/// for rows whose deletion is pending, e.g. deleted in an earlier block of the same
+/// flush, which are deleted, then inserted again.
+fn schedule_insert(
+    ops: &mut HashMap<String, Operation>,
+    table_name: String,
+    primary_key: String,
+    mut insert_op: Operation,
+) -> Result<EntriesChange, DBError> {
+    match ops.get(&primary_key).map(|op| op.op_type()) {
+        Some(OperationType::Delete) => {
+            insert_op.set_replace(true);
+            ops.insert(primary_key, insert_op);
+            Ok(EntriesChange::Unchanged)
+        }
+        Some(_) => Err(DBError::PrimaryKeyAlreadyScheduleForOperation {
+            table_name,
+            primary_key,
+        }),
+        None => {
+            ops.insert(primary_key, insert_op);
+            Ok(EntriesChange::Added)
+        }
+    }
+}
+
+/// Schedules `update_op` for `primary_key` among the pending operations `ops` of table
+/// `table_name`. If an insert or update is already pending for the same primary key, the
+/// new values are merged into it, so that a single query is sent on flush. An update
+/// without columns, e.g. of a record without fields, changes nothing, and is not
+/// scheduled, as it has no valid query.
+fn schedule_update(
+    ops: &mut HashMap<String, Operation>,
+    table_name: String,
+    primary_key: String,
+    update_op: Operation,
+) -> Result<EntriesChange, DBError> {
+    if update_op.data().is_empty() {
+        return Ok(EntriesChange::Unchanged);
+    }
+    match ops.get_mut(&primary_key) {
+        Some(op) if *op.op_type() == OperationType::Delete => {
+            Err(DBError::PrimaryKeyAlreadyScheduleForOperation {
+                table_name,
+                primary_key,
+            })
+        }
+        Some(op) => {
+            op.merge_data(update_op.data().clone());
+            Ok(EntriesChange::Unchanged)
+        }
+        None => {
+            ops.insert(primary_key, update_op);
+            Ok(EntriesChange::Added)
+        }
     }
 }
 
@@ -111,27 +263,323 @@ impl DBLoader {
 
         let col_type = table_cols
             .get(column_name)
-            .ok_or(DBError::ColumnNotFound(String::from(column_name)))?;
+            .ok_or_else(|| DBError::ColumnNotFound {
+                table_name: String::from(table_name),
+                column_name: String::from(column_name),
+            })?;
 
         ColumnValue::parse_type(col_type.clone(), value)
     }
 
-    /// Given a table name, a primary key and provided data, it creates a
-    /// new operation, of type `Insert`.
-    fn new_insert_operation(
+    /// Adds the number, id and timestamp of the current block to `data`, for each of
+    /// the [`BLOCK_NUM_COLUMN`], [`BLOCK_ID_COLUMN`] and [`BLOCK_TIMESTAMP_COLUMN`]
+    /// columns of table `table_name`.
+    fn with_block_columns(
+        &self,
+        table_name: &str,
+        mut data: HashMap<String, ColumnValue>,
+    ) -> HashMap<String, ColumnValue> {
+        let (columns, block) = match (self.get_tables().get(table_name), self.get_block()) {
+            (Some(columns), Some(block)) => (columns, block),
+            _ => return data,
+        };
+
+        if columns.contains_key(BLOCK_NUM_COLUMN) {
+            data.insert(
+                BLOCK_NUM_COLUMN.to_string(),
+                ColumnValue::BigInt(BigInt::set_inner(block.num as i64)),
+            );
+        }
+        if columns.contains_key(BLOCK_ID_COLUMN) {
+            data.insert(
+                BLOCK_ID_COLUMN.to_string(),
+                ColumnValue::Text(Text::set_inner(block.id.clone())),
+            );
+        }
+        if let (true, Some(timestamp)) = (
+            columns.contains_key(BLOCK_TIMESTAMP_COLUMN),
+            self.get_block_timestamp(),
+        ) {
+            data.insert(
+                BLOCK_TIMESTAMP_COLUMN.to_string(),
+                ColumnValue::Timestamptz(Timestamptz::set_inner(timestamp.into())),
+            );
+        }
+        data
+    }
+
+    /// Converts each value of `data` into the type of its column, in table `table_name`,
+    /// so that invalid values, including `NULL` values in `NOT NULL` columns, are reported
+    /// when the operation is scheduled, rather than on flush.
+    fn coerce_data(
+        &self,
+        table_name: &str,
+        data: HashMap<String, ColumnValue>,
+    ) -> Result<HashMap<String, ColumnValue>, DBError> {
+        let table_cols = self
+            .get_tables()
+            .get(table_name)
+            .ok_or(DBError::TableNotFound(String::from(table_name)))?;
+
+        data.into_iter()
+            .map(|(colname, value)| {
+                let col_type = table_cols
+                    .get(&colname)
+                    .ok_or_else(|| DBError::ColumnNotFound {
+                        table_name: String::from(table_name),
+                        column_name: colname.clone(),
+                    })?;
+                if matches!(value, ColumnValue::Null(_)) && !self.is_nullable(table_name, &colname)
+                {
+                    return Err(DBError::NullValueInNotNullColumn {
+                        table_name: String::from(table_name),
+                        column_name: colname,
+                    });
+                }
+                let value = value
+                    .coerce(col_type)
+                    .map_err(|e| DBError::InvalidColumnValue {
+                        table_name: String::from(table_name),
+                        column_name: colname.clone(),
+                        error: e.to_string(),
+                    })?;
+                Ok((colname, value))
+            })
+            .collect()
+    }
+
+    /// Maps a record id onto the primary key columns of a table, with types
+    /// already parsed in. For a single column primary key, the whole id is its value.
+    /// For a composite primary key, the id is split on [`PRIMARY_KEY_SEPARATOR`],
+    /// starting from the right, so that only the first key column value may
+    /// contain the separator (e.g. `ipfs://Qm...:app_id`).
+    pub fn get_primary_key_values(
+        &self,
+        table_name: &str,
+        primary_key: &str,
+    ) -> Result<Vec<(String, ColumnValue)>, DBError> {
+        let primary_key_colnames = self
+            .get_primary_key_column_names(table_name)
+            .ok_or(DBError::TableNotFound(String::from(table_name)))?;
+
+        let mut values = primary_key
+            .rsplitn(primary_key_colnames.len(), PRIMARY_KEY_SEPARATOR)
+            .collect::<Vec<_>>();
+        values.reverse();
+
+        if values.len() != primary_key_colnames.len() {
+            return Err(DBError::InvalidPrimaryKey {
+                table_name: String::from(table_name),
+                primary_key: String::from(primary_key),
+                columns: primary_key_colnames.len(),
+            });
+        }
+
+        primary_key_colnames
+            .into_iter()
+            .zip(values)
+            .map(|(colname, value)| {
+                let value = self.get_type(table_name, &colname, String::from(value))?;
+                Ok((colname, value))
+            })
+            .collect()
+    }
+
+    /// Given a table name, an operation type, a primary key and provided data,
+    /// it creates a new operation.
+    fn new_operation(
         &self,
         table_name: String,
-        primary_key: ColumnValue,
+        op_type: OperationType,
+        primary_key: Vec<(String, ColumnValue)>,
         data: HashMap<String, ColumnValue>,
     ) -> Operation {
         Operation::new(
             String::from(self.get_schema()),
-            table_name.clone(),
-            self.get_primary_key_column_name(&table_name)
-                .expect(format!("Primary key column not valid for table: {}", table_name).as_ref()),
-            OperationType::Insert,
+            table_name,
+            op_type,
             primary_key,
             data,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(op_type: OperationType, content: &str) -> Operation {
+        Operation::new(
+            "my_schema".to_string(),
+            "posts".to_string(),
+            op_type,
+            vec![(
+                "id".to_string(),
+                ColumnValue::Text(Text::set_inner("post1".to_string())),
+            )],
+            HashMap::from([(
+                "content".to_string(),
+                ColumnValue::Text(Text::set_inner(content.to_string())),
+            )]),
+        )
+    }
+
+    #[test]
+    fn it_works_with_primary_key() {
+        let primary_key = vec![(
+            "id".to_string(),
+            ColumnValue::Text(Text::set_inner("4f2a".to_string())),
+        )];
+        let data = HashMap::from([
+            (
+                "id".to_string(),
+                ColumnValue::Text(Text::set_inner("post1".to_string())),
+            ),
+            (
+                "content".to_string(),
+                ColumnValue::Text(Text::set_inner("gm".to_string())),
+            ),
+        ]);
+
+        assert_eq!(
+            with_primary_key(data, &primary_key),
+            HashMap::from([
+                (
+                    "id".to_string(),
+                    ColumnValue::Text(Text::set_inner("4f2a".to_string())),
+                ),
+                (
+                    "content".to_string(),
+                    ColumnValue::Text(Text::set_inner("gm".to_string())),
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_works_schedule_update() {
+        let mut ops = HashMap::new();
+        let key = "post1".to_string();
+        let empty_update = Operation::new(
+            "my_schema".to_string(),
+            "posts".to_string(),
+            OperationType::Update,
+            vec![(
+                "id".to_string(),
+                ColumnValue::Text(Text::set_inner("post1".to_string())),
+            )],
+            HashMap::new(),
+        );
+
+        // an update without columns is skipped, rather than flushed as `SET` nothing
+        assert_eq!(
+            schedule_update(&mut ops, "posts".to_string(), key.clone(), empty_update).unwrap(),
+            EntriesChange::Unchanged
+        );
+        assert!(ops.is_empty());
+
+        assert_eq!(
+            schedule_update(
+                &mut ops,
+                "posts".to_string(),
+                key.clone(),
+                operation(OperationType::Update, "new")
+            )
+            .unwrap(),
+            EntriesChange::Added
+        );
+        assert_eq!(
+            schedule_update(
+                &mut ops,
+                "posts".to_string(),
+                key.clone(),
+                operation(OperationType::Update, "newer")
+            )
+            .unwrap(),
+            EntriesChange::Unchanged
+        );
+        assert_eq!(
+            ops[&key].build_query().sql,
+            "UPDATE my_schema.posts SET content=$1 WHERE id=$2"
+        );
+        assert_eq!(
+            ops[&key].data()["content"],
+            ColumnValue::Text(Text::set_inner("newer".to_string()))
+        );
+    }
+
+    #[test]
+    fn it_works_schedule_delete_then_insert() {
+        let mut ops = HashMap::new();
+        let key = "post1".to_string();
+
+        assert_eq!(
+            schedule_delete(&mut ops, key.clone(), operation(OperationType::Delete, "")),
+            EntriesChange::Added
+        );
+        // the key is inserted again, in a later block of the same flush
+        assert_eq!(
+            schedule_insert(
+                &mut ops,
+                "posts".to_string(),
+                key.clone(),
+                operation(OperationType::Insert, "new")
+            )
+            .unwrap(),
+            EntriesChange::Unchanged
+        );
+        let op = &ops[&key];
+        assert_eq!(op.op_type(), &OperationType::Insert);
+        assert!(op.replace());
+        assert_eq!(
+            op.build_queries()
+                .into_iter()
+                .map(|q| q.sql)
+                .collect::<Vec<_>>(),
+            vec![
+                "DELETE FROM my_schema.posts WHERE id = $1",
+                "INSERT INTO my_schema.posts (content) VALUES ($1)"
+            ]
+        );
+
+        // the replacing insert is still append only
+        assert!(matches!(
+            schedule_insert(
+                &mut ops,
+                "posts".to_string(),
+                key.clone(),
+                operation(OperationType::Insert, "other")
+            ),
+            Err(DBError::PrimaryKeyAlreadyScheduleForOperation { .. })
+        ));
+
+        // deleting it again still deletes the row of the DB
+        assert_eq!(
+            schedule_delete(&mut ops, key.clone(), operation(OperationType::Delete, "")),
+            EntriesChange::Unchanged
+        );
+        assert_eq!(ops[&key].op_type(), &OperationType::Delete);
+    }
+
+    #[test]
+    fn it_works_schedule_insert_then_delete() {
+        let mut ops = HashMap::new();
+        let key = "post1".to_string();
+
+        assert_eq!(
+            schedule_insert(
+                &mut ops,
+                "posts".to_string(),
+                key.clone(),
+                operation(OperationType::Insert, "new")
+            )
+            .unwrap(),
+            EntriesChange::Added
+        );
+        assert_eq!(
+            schedule_delete(&mut ops, key.clone(), operation(OperationType::Delete, "")),
+            EntriesChange::Removed
+        );
+        assert!(ops.is_empty());
+    }
+}
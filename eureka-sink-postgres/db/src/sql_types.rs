@@ -1,5 +1,9 @@
-use bigdecimal::BigDecimal;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use diesel::{
+    pg::{data_types::PgInterval, Pg},
+    query_builder::{BoxedSqlQuery, SqlQuery},
+};
 use std::convert::TryFrom;
 
 use crate::error::DBError;
@@ -106,6 +110,91 @@ pub struct Interval {
     inner: pg_interval::Interval,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`Timestamptz`] instance.
+pub struct Timestamptz {
+    inner: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`Uuid`] instance.
+pub struct Uuid {
+    inner: uuid::Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`Inet`] instance.
+pub struct Inet {
+    inner: ipnetwork::IpNetwork,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`Json`] instance.
+pub struct Json {
+    inner: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`Jsonb`] instance.
+pub struct Jsonb {
+    inner: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// A SQL `NULL` value, which is typed once the type of its column
+/// is known (see [`ColumnValue::coerce`]).
+pub struct Null {
+    column_type: Option<ColumnType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`BoolArray`] instance.
+pub struct BoolArray {
+    inner: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`SmallIntArray`] instance.
+pub struct SmallIntArray {
+    inner: Vec<i16>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`IntegerArray`] instance.
+pub struct IntegerArray {
+    inner: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`BigIntArray`] instance.
+pub struct BigIntArray {
+    inner: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`DoubleArray`] instance.
+pub struct DoubleArray {
+    inner: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`NumericArray`] instance.
+pub struct NumericArray {
+    inner: Vec<BigDecimal>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`TextArray`] instance.
+pub struct TextArray {
+    inner: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A diesel compatible [`BinaryArray`] instance.
+pub struct BinaryArray {
+    inner: Vec<Vec<u8>>,
+}
+
 sql_type_impl!(Bool, diesel::sql_types::Bool, bool);
 sql_type_impl!(SmallInt, diesel::sql_types::SmallInt, i16);
 sql_type_impl!(Integer, diesel::sql_types::Integer, i32);
@@ -119,6 +208,51 @@ sql_type_impl!(Date, diesel::sql_types::Date, NaiveDate);
 sql_type_impl!(Timestamp, diesel::sql_types::Timestamp, NaiveDateTime);
 sql_type_impl!(Time, diesel::sql_types::Time, NaiveTime);
 sql_type_impl!(Interval, diesel::sql_types::Interval, pg_interval::Interval);
+sql_type_impl!(Timestamptz, diesel::sql_types::Timestamptz, DateTime<Utc>);
+sql_type_impl!(Uuid, diesel::sql_types::Uuid, uuid::Uuid);
+sql_type_impl!(Inet, diesel::sql_types::Inet, ipnetwork::IpNetwork);
+sql_type_impl!(Json, diesel::sql_types::Json, serde_json::Value);
+sql_type_impl!(Jsonb, diesel::sql_types::Jsonb, serde_json::Value);
+sql_type_impl!(
+    BoolArray,
+    diesel::sql_types::Array<diesel::sql_types::Bool>,
+    Vec<bool>
+);
+sql_type_impl!(
+    SmallIntArray,
+    diesel::sql_types::Array<diesel::sql_types::SmallInt>,
+    Vec<i16>
+);
+sql_type_impl!(
+    IntegerArray,
+    diesel::sql_types::Array<diesel::sql_types::Integer>,
+    Vec<i32>
+);
+sql_type_impl!(
+    BigIntArray,
+    diesel::sql_types::Array<diesel::sql_types::BigInt>,
+    Vec<i64>
+);
+sql_type_impl!(
+    DoubleArray,
+    diesel::sql_types::Array<diesel::sql_types::Double>,
+    Vec<f64>
+);
+sql_type_impl!(
+    NumericArray,
+    diesel::sql_types::Array<diesel::sql_types::Numeric>,
+    Vec<BigDecimal>
+);
+sql_type_impl!(
+    TextArray,
+    diesel::sql_types::Array<diesel::sql_types::Text>,
+    Vec<String>
+);
+sql_type_impl!(
+    BinaryArray,
+    diesel::sql_types::Array<diesel::sql_types::Binary>,
+    Vec<Vec<u8>>
+);
 
 /// A diesel compatible [`Int2`] instance.
 pub type Int2 = SmallInt;
@@ -171,6 +305,9 @@ pub type Varbinary = Binary;
 /// A diesel compatible [`Bit`] instance.
 pub type Bit = Binary;
 
+/// A diesel compatible [`Bytea`] instance.
+pub type Bytea = Binary;
+
 #[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 /// [`ColumnValue`] encapsulates a list of our custom diesel
@@ -202,10 +339,25 @@ pub enum ColumnValue {
     LongBlob(LongBlob),
     Varbinary(Varbinary),
     Bit(Bit),
+    Bytea(Bytea),
     Date(Date),
     Interval(Interval),
     Time(Time),
     Timestamp(Timestamp),
+    Timestamptz(Timestamptz),
+    Uuid(Uuid),
+    Inet(Inet),
+    Json(Json),
+    Jsonb(Jsonb),
+    BoolArray(BoolArray),
+    SmallIntArray(SmallIntArray),
+    IntegerArray(IntegerArray),
+    BigIntArray(BigIntArray),
+    DoubleArray(DoubleArray),
+    NumericArray(NumericArray),
+    TextArray(TextArray),
+    BinaryArray(BinaryArray),
+    Null(Null),
 }
 
 impl ColumnValue {
@@ -234,18 +386,514 @@ impl ColumnValue {
             Self::TinyText(t) => format!("'{}'", t.get_inner()),
             Self::MediumText(t) => format!("'{}'", t.get_inner()),
             Self::LongText(t) => format!("'{}'", t.get_inner()),
-            Self::Binary(b) => format!("{:?}", b.get_inner()),
-            Self::TinyBlob(b) => format!("{:?}", b.get_inner()),
-            Self::Blob(b) => format!("{:?}", b.get_inner()),
-            Self::MediumBlob(b) => format!("{:?}", b.get_inner()),
-            Self::LongBlob(b) => format!("{:?}", b.get_inner()),
-            Self::Varbinary(b) => format!("{:?}", b.get_inner()),
-            Self::Bit(b) => format!("{:?}", b.get_inner()),
+            Self::Binary(b) => to_bytea_literal(b.get_inner()),
+            Self::TinyBlob(b) => to_bytea_literal(b.get_inner()),
+            Self::Blob(b) => to_bytea_literal(b.get_inner()),
+            Self::MediumBlob(b) => to_bytea_literal(b.get_inner()),
+            Self::LongBlob(b) => to_bytea_literal(b.get_inner()),
+            Self::Varbinary(b) => to_bytea_literal(b.get_inner()),
+            Self::Bit(b) => to_bytea_literal(b.get_inner()),
+            Self::Bytea(b) => to_bytea_literal(b.get_inner()),
             Self::Date(d) => format!("'{}'", d.get_inner()),
-            Self::Interval(_) => panic!("Not implemented!"),
+            Self::Interval(i) => format!("interval '{}'", i.get_inner().to_iso_8601()),
             Self::Time(t) => format!("'{}'", t.get_inner()),
             Self::Timestamp(t) => format!("'{}'", t.get_inner()),
+            Self::Timestamptz(t) => format!("'{}'", t.get_inner().to_rfc3339()),
+            Self::Uuid(u) => format!("'{}'", u.get_inner()),
+            Self::Inet(i) => format!("'{}'", i.get_inner()),
+            Self::Json(j) => format!("'{}'", j.get_inner()),
+            Self::Jsonb(j) => format!("'{}'", j.get_inner()),
+            Self::BoolArray(_)
+            | Self::SmallIntArray(_)
+            | Self::IntegerArray(_)
+            | Self::BigIntArray(_)
+            | Self::DoubleArray(_)
+            | Self::NumericArray(_)
+            | Self::TextArray(_)
+            | Self::BinaryArray(_) => format!("'{}'", self.to_array_literal()),
+            Self::Null(_) => String::from("NULL"),
+        }
+    }
+
+    /// Given an array [`ColumnValue`], it produces its PostgreSQL array literal,
+    /// e.g. `{1,2}` or `{"a","b"}`. Non-array values are formatted as a single element.
+    fn to_array_literal(&self) -> String {
+        fn literal<T>(values: &[T], element: impl Fn(&T) -> String) -> String {
+            format!(
+                "{{{}}}",
+                values.iter().map(element).collect::<Vec<_>>().join(",")
+            )
+        }
+        fn quote(value: &str) -> String {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
         }
+
+        match self {
+            Self::BoolArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::SmallIntArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::IntegerArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::BigIntArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::DoubleArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::NumericArray(a) => literal(a.get_inner(), |v| v.to_string()),
+            Self::TextArray(a) => literal(a.get_inner(), |v| quote(v)),
+            Self::BinaryArray(a) => {
+                literal(a.get_inner(), |v| quote(&format!("\\x{}", hex::encode(v))))
+            }
+            _ => format!("{{{}}}", self.to_string().trim_matches('\'')),
+        }
+    }
+
+    /// Builds an array [`ColumnValue`] out of its elements, which are expected to be
+    /// of a same type. The array type is inferred from the first element, thus
+    /// empty arrays are rejected (see [`ColumnValue::new_array_of`]).
+    pub fn new_array(elements: Vec<ColumnValue>) -> Result<Self, DBError> {
+        let array_type = match elements.first() {
+            Some(Self::Bool(_)) => ColumnType::BoolArray,
+            Some(Self::SmallInt(_) | Self::Int2(_)) => ColumnType::SmallIntArray,
+            Some(Self::Integer(_) | Self::Int4(_)) => ColumnType::IntegerArray,
+            Some(Self::BigInt(_) | Self::Int8(_)) => ColumnType::BigIntArray,
+            Some(Self::Float(_) | Self::Float4(_) | Self::Double(_) | Self::Float8(_)) => {
+                ColumnType::DoubleArray
+            }
+            Some(Self::Numeric(_) | Self::Decimal(_)) => ColumnType::NumericArray,
+            Some(
+                Self::Text(_)
+                | Self::VarChar(_)
+                | Self::Char(_)
+                | Self::TinyText(_)
+                | Self::MediumText(_)
+                | Self::LongText(_),
+            ) => ColumnType::TextArray,
+            Some(
+                Self::Binary(_)
+                | Self::TinyBlob(_)
+                | Self::Blob(_)
+                | Self::MediumBlob(_)
+                | Self::LongBlob(_)
+                | Self::Varbinary(_)
+                | Self::Bit(_)
+                | Self::Bytea(_),
+            ) => ColumnType::BinaryArray,
+            Some(value) => {
+                return Err(DBError::InvalidColumnDataType(format!(
+                    "arrays of {:?} are not supported",
+                    value
+                )))
+            }
+            None => {
+                return Err(DBError::InvalidColumnDataType(
+                    "the type of an empty array can not be inferred".to_string(),
+                ))
+            }
+        };
+
+        Self::new_array_of(&array_type, elements)
+    }
+
+    /// Builds an array [`ColumnValue`] of type `array_type` out of its elements.
+    pub fn new_array_of(
+        array_type: &ColumnType,
+        elements: Vec<ColumnValue>,
+    ) -> Result<Self, DBError> {
+        let mismatch = |value: ColumnValue| {
+            DBError::InvalidColumnDataType(format!("{:?} can not hold {:?}", array_type, value))
+        };
+
+        Ok(match array_type {
+            ColumnType::BoolArray => Self::BoolArray(BoolArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Bool(b) => Ok(b.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::SmallIntArray => Self::SmallIntArray(SmallIntArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::SmallInt(i) | Self::Int2(i) => Ok(i.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::IntegerArray => Self::IntegerArray(IntegerArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Integer(i) | Self::Int4(i) => Ok(i.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::BigIntArray => Self::BigIntArray(BigIntArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::BigInt(i) | Self::Int8(i) => Ok(i.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::DoubleArray => Self::DoubleArray(DoubleArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Float(f) | Self::Float4(f) => Ok(f64::from(f.inner)),
+                        Self::Double(d) | Self::Float8(d) => Ok(d.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::NumericArray => Self::NumericArray(NumericArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Numeric(n) | Self::Decimal(n) => Ok(n.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::TextArray => Self::TextArray(TextArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Text(t)
+                        | Self::VarChar(t)
+                        | Self::Char(t)
+                        | Self::TinyText(t)
+                        | Self::MediumText(t)
+                        | Self::LongText(t) => Ok(t.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            ColumnType::BinaryArray => Self::BinaryArray(BinaryArray {
+                inner: elements
+                    .into_iter()
+                    .map(|v| match v {
+                        Self::Binary(b)
+                        | Self::TinyBlob(b)
+                        | Self::Blob(b)
+                        | Self::MediumBlob(b)
+                        | Self::LongBlob(b)
+                        | Self::Varbinary(b)
+                        | Self::Bit(b)
+                        | Self::Bytea(b) => Ok(b.inner),
+                        v => Err(mismatch(v)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            _ => {
+                return Err(DBError::InvalidColumnDataType(format!(
+                    "{:?} is not an array type",
+                    array_type
+                )))
+            }
+        })
+    }
+
+    /// Converts a numeric value into a [`ColumnValue`] of type `column_type`, e.g. an
+    /// `uint256` amount into a `numeric` column. Values which can not be held by the
+    /// column type without loss, such as out of range or fractional values for integer
+    /// types, are rejected.
+    pub fn from_numeric(value: BigDecimal, column_type: &ColumnType) -> Result<Self, DBError> {
+        let out_of_range = || DBError::ValueOutOfRange {
+            value: value.to_string(),
+            column_type: format!("{:?}", column_type),
+        };
+        let is_integer = value.is_integer();
+
+        Ok(match column_type {
+            ColumnType::SmallInt | ColumnType::Int2 => {
+                let inner = value
+                    .to_i16()
+                    .filter(|_| is_integer)
+                    .ok_or_else(out_of_range)?;
+                match column_type {
+                    ColumnType::Int2 => Self::Int2(Int2 { inner }),
+                    _ => Self::SmallInt(SmallInt { inner }),
+                }
+            }
+            ColumnType::Integer | ColumnType::Int4 => {
+                let inner = value
+                    .to_i32()
+                    .filter(|_| is_integer)
+                    .ok_or_else(out_of_range)?;
+                match column_type {
+                    ColumnType::Int4 => Self::Int4(Int4 { inner }),
+                    _ => Self::Integer(Integer { inner }),
+                }
+            }
+            ColumnType::BigInt | ColumnType::Int8 => {
+                let inner = value
+                    .to_i64()
+                    .filter(|_| is_integer)
+                    .ok_or_else(out_of_range)?;
+                match column_type {
+                    ColumnType::Int8 => Self::Int8(Int8 { inner }),
+                    _ => Self::BigInt(BigInt { inner }),
+                }
+            }
+            ColumnType::Float | ColumnType::Float4 => {
+                let inner = value
+                    .to_f32()
+                    .filter(|f| f.is_finite())
+                    .ok_or_else(out_of_range)?;
+                match column_type {
+                    ColumnType::Float4 => Self::Float4(Float4 { inner }),
+                    _ => Self::Float(Float { inner }),
+                }
+            }
+            ColumnType::Double | ColumnType::Float8 => {
+                let inner = value
+                    .to_f64()
+                    .filter(|f| f.is_finite())
+                    .ok_or_else(out_of_range)?;
+                match column_type {
+                    ColumnType::Float8 => Self::Float8(Float8 { inner }),
+                    _ => Self::Double(Double { inner }),
+                }
+            }
+            ColumnType::Numeric => Self::Numeric(Numeric { inner: value }),
+            ColumnType::Decimal => Self::Decimal(Decimal { inner: value }),
+            ColumnType::Text
+            | ColumnType::VarChar
+            | ColumnType::Char
+            | ColumnType::TinyText
+            | ColumnType::MediumText
+            | ColumnType::LongText => Self::parse_type(column_type.clone(), value.to_string())?,
+            _ => {
+                return Err(DBError::InvalidColumnDataType(format!(
+                    "{:?} can not hold numeric value {}",
+                    column_type, value
+                )))
+            }
+        })
+    }
+
+    /// Parses a numeric value, e.g. a `bigint` or `bigdecimal` string, into a
+    /// [`ColumnValue`] of type `column_type` (see [`ColumnValue::from_numeric`]).
+    pub fn parse_numeric(value: &str, column_type: &ColumnType) -> Result<Self, DBError> {
+        let numeric = value
+            .parse::<BigDecimal>()
+            .map_err(|_| DBError::FailedParseString(value.to_string()))?;
+        Self::from_numeric(numeric, column_type)
+    }
+
+    /// Converts a [`ColumnValue`] into a value of type `column_type`, e.g. an integer
+    /// into a `numeric` column, or a string into a `timestamp` column. Numeric values
+    /// are converted without loss (see [`ColumnValue::from_numeric`]), while other
+    /// values are parsed from their literal (see [`ColumnValue::parse_type`]).
+    pub fn coerce(self, column_type: &ColumnType) -> Result<Self, DBError> {
+        let mismatch = |value: &ColumnValue| {
+            DBError::InvalidColumnDataType(format!("{:?} can not hold {:?}", column_type, value))
+        };
+
+        if let Self::Null(_) = self {
+            return Ok(Self::Null(Null {
+                column_type: Some(column_type.clone()),
+            }));
+        }
+
+        if column_type.element_type().is_some() {
+            let elements = self
+                .clone()
+                .into_elements()
+                .ok_or_else(|| mismatch(&self))?;
+            return Self::new_array_of(
+                column_type,
+                elements
+                    .into_iter()
+                    .map(|e| e.coerce(&column_type.element_type().unwrap()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        match self {
+            Self::SmallInt(i) | Self::Int2(i) => {
+                Self::from_numeric(BigDecimal::from(i.inner), column_type)
+            }
+            Self::Integer(i) | Self::Int4(i) => {
+                Self::from_numeric(BigDecimal::from(i.inner), column_type)
+            }
+            Self::BigInt(i) | Self::Int8(i) => {
+                Self::from_numeric(BigDecimal::from(i.inner), column_type)
+            }
+            Self::Numeric(n) | Self::Decimal(n) => Self::from_numeric(n.inner, column_type),
+            Self::Float(f) | Self::Float4(f) => match column_type {
+                ColumnType::Float => Ok(Self::Float(f)),
+                ColumnType::Float4 => Ok(Self::Float4(f)),
+                _ => Self::Double(Double {
+                    inner: f64::from(f.inner),
+                })
+                .coerce(column_type),
+            },
+            Self::Double(d) | Self::Float8(d) => match column_type {
+                ColumnType::Double => Ok(Self::Double(d)),
+                ColumnType::Float8 => Ok(Self::Float8(d)),
+                _ => {
+                    let value = BigDecimal::from_f64(d.inner)
+                        .ok_or_else(|| mismatch(&Self::Double(d.clone())))?;
+                    Self::from_numeric(value, column_type)
+                }
+            },
+            Self::Text(t)
+            | Self::VarChar(t)
+            | Self::Char(t)
+            | Self::TinyText(t)
+            | Self::MediumText(t)
+            | Self::LongText(t) => Self::parse_type(column_type.clone(), t.inner),
+            Self::Interval(i) => match column_type {
+                ColumnType::Interval => Ok(Self::Interval(i)),
+                _ => Err(mismatch(&Self::Interval(i))),
+            },
+            Self::BoolArray(_)
+            | Self::SmallIntArray(_)
+            | Self::IntegerArray(_)
+            | Self::BigIntArray(_)
+            | Self::DoubleArray(_)
+            | Self::NumericArray(_)
+            | Self::TextArray(_)
+            | Self::BinaryArray(_) => Err(mismatch(&self)),
+            // boolean, binary, date and time, uuid, inet and json values
+            _ => Self::parse_type(
+                column_type.clone(),
+                self.to_string().trim_matches('\'').to_string(),
+            ),
+        }
+    }
+
+    /// Splits an array [`ColumnValue`] into its elements.
+    /// Returns `None` for non-array values.
+    fn into_elements(self) -> Option<Vec<ColumnValue>> {
+        fn elements<T>(values: Vec<T>, element: impl Fn(T) -> ColumnValue) -> Vec<ColumnValue> {
+            values.into_iter().map(element).collect()
+        }
+
+        Some(match self {
+            Self::BoolArray(a) => elements(a.inner, |inner| Self::Bool(Bool { inner })),
+            Self::SmallIntArray(a) => elements(a.inner, |inner| Self::SmallInt(SmallInt { inner })),
+            Self::IntegerArray(a) => elements(a.inner, |inner| Self::Integer(Integer { inner })),
+            Self::BigIntArray(a) => elements(a.inner, |inner| Self::BigInt(BigInt { inner })),
+            Self::DoubleArray(a) => elements(a.inner, |inner| Self::Double(Double { inner })),
+            Self::NumericArray(a) => elements(a.inner, |inner| Self::Numeric(Numeric { inner })),
+            Self::TextArray(a) => elements(a.inner, |inner| Self::Text(Text { inner })),
+            Self::BinaryArray(a) => elements(a.inner, |inner| Self::Binary(Binary { inner })),
+            _ => return None,
+        })
+    }
+
+    /// Given an instance of [`ColumnValue`] it produces a string with its value,
+    /// encoded as a field of `COPY ... FROM STDIN` in text format.
+    pub fn to_copy_text(&self) -> Result<String, DBError> {
+        Ok(match self {
+            Self::Bool(b) => String::from(if *b.get_inner() { "t" } else { "f" }),
+            Self::Text(t)
+            | Self::VarChar(t)
+            | Self::Char(t)
+            | Self::TinyText(t)
+            | Self::MediumText(t)
+            | Self::LongText(t) => escape_copy_text(t.get_inner()),
+            Self::Json(j) => escape_copy_text(&j.get_inner().to_string()),
+            Self::Jsonb(j) => escape_copy_text(&j.get_inner().to_string()),
+            Self::Binary(b)
+            | Self::TinyBlob(b)
+            | Self::Blob(b)
+            | Self::MediumBlob(b)
+            | Self::LongBlob(b)
+            | Self::Varbinary(b)
+            | Self::Bit(b)
+            | Self::Bytea(b) => format!("\\\\x{}", hex::encode(b.get_inner())),
+            Self::Interval(i) => i.get_inner().to_iso_8601(),
+            Self::BoolArray(_)
+            | Self::SmallIntArray(_)
+            | Self::IntegerArray(_)
+            | Self::BigIntArray(_)
+            | Self::DoubleArray(_)
+            | Self::NumericArray(_)
+            | Self::TextArray(_)
+            | Self::BinaryArray(_) => escape_copy_text(&self.to_array_literal()),
+            Self::Null(_) => String::from("\\N"),
+            // numeric, date and time values are not quoted
+            _ => self.to_string().trim_matches('\'').to_string(),
+        })
+    }
+
+    /// Binds the value of a [`ColumnValue`] instance to the next placeholder
+    /// of a boxed SQL query, with its corresponding diesel SQL type.
+    pub fn bind<'f>(
+        &self,
+        query: BoxedSqlQuery<'f, Pg, SqlQuery>,
+    ) -> Result<BoxedSqlQuery<'f, Pg, SqlQuery>, DBError> {
+        Ok(match self {
+            Self::Bool(b) => query.bind::<<Bool as Sql>::T, _>(*b.get_inner()),
+            Self::SmallInt(i) => query.bind::<<SmallInt as Sql>::T, _>(*i.get_inner()),
+            Self::Int2(i) => query.bind::<<Int2 as Sql>::T, _>(*i.get_inner()),
+            Self::Integer(i) => query.bind::<<Integer as Sql>::T, _>(*i.get_inner()),
+            Self::Int4(i) => query.bind::<<Int4 as Sql>::T, _>(*i.get_inner()),
+            Self::BigInt(i) => query.bind::<<BigInt as Sql>::T, _>(*i.get_inner()),
+            Self::Int8(i) => query.bind::<<Int8 as Sql>::T, _>(*i.get_inner()),
+            Self::Float(f) => query.bind::<<Float as Sql>::T, _>(*f.get_inner()),
+            Self::Float4(f) => query.bind::<<Float4 as Sql>::T, _>(*f.get_inner()),
+            Self::Double(d) => query.bind::<<Double as Sql>::T, _>(*d.get_inner()),
+            Self::Float8(f) => query.bind::<<Float8 as Sql>::T, _>(*f.get_inner()),
+            Self::Numeric(n) => query.bind::<<Numeric as Sql>::T, _>(n.get_inner().clone()),
+            Self::Decimal(d) => query.bind::<<Decimal as Sql>::T, _>(d.get_inner().clone()),
+            Self::Text(t) => query.bind::<<Text as Sql>::T, _>(t.get_inner().clone()),
+            Self::VarChar(v) => query.bind::<<VarChar as Sql>::T, _>(v.get_inner().clone()),
+            Self::Char(c) => query.bind::<<Char as Sql>::T, _>(c.get_inner().clone()),
+            Self::TinyText(t) => query.bind::<<TinyText as Sql>::T, _>(t.get_inner().clone()),
+            Self::MediumText(t) => query.bind::<<MediumText as Sql>::T, _>(t.get_inner().clone()),
+            Self::LongText(t) => query.bind::<<LongText as Sql>::T, _>(t.get_inner().clone()),
+            Self::Binary(b) => query.bind::<<Binary as Sql>::T, _>(b.get_inner().clone()),
+            Self::TinyBlob(b) => query.bind::<<TinyBlob as Sql>::T, _>(b.get_inner().clone()),
+            Self::Blob(b) => query.bind::<<Blob as Sql>::T, _>(b.get_inner().clone()),
+            Self::MediumBlob(b) => query.bind::<<MediumBlob as Sql>::T, _>(b.get_inner().clone()),
+            Self::LongBlob(b) => query.bind::<<LongBlob as Sql>::T, _>(b.get_inner().clone()),
+            Self::Varbinary(b) => query.bind::<<Varbinary as Sql>::T, _>(b.get_inner().clone()),
+            Self::Bit(b) => query.bind::<<Bit as Sql>::T, _>(b.get_inner().clone()),
+            Self::Bytea(b) => query.bind::<<Bytea as Sql>::T, _>(b.get_inner().clone()),
+            Self::Date(d) => query.bind::<<Date as Sql>::T, _>(*d.get_inner()),
+            Self::Interval(i) => {
+                let interval = i.get_inner();
+                query.bind::<<Interval as Sql>::T, _>(PgInterval::new(
+                    interval.microseconds,
+                    interval.days,
+                    interval.months,
+                ))
+            }
+            Self::Time(t) => query.bind::<<Time as Sql>::T, _>(*t.get_inner()),
+            Self::Timestamp(t) => query.bind::<<Timestamp as Sql>::T, _>(*t.get_inner()),
+            Self::Timestamptz(t) => query.bind::<<Timestamptz as Sql>::T, _>(*t.get_inner()),
+            Self::Uuid(u) => query.bind::<<Uuid as Sql>::T, _>(*u.get_inner()),
+            Self::Inet(i) => query.bind::<<Inet as Sql>::T, _>(*i.get_inner()),
+            Self::Json(j) => query.bind::<<Json as Sql>::T, _>(j.get_inner().clone()),
+            Self::Jsonb(j) => query.bind::<<Jsonb as Sql>::T, _>(j.get_inner().clone()),
+            Self::BoolArray(a) => query.bind::<<BoolArray as Sql>::T, _>(a.get_inner().clone()),
+            Self::SmallIntArray(a) => {
+                query.bind::<<SmallIntArray as Sql>::T, _>(a.get_inner().clone())
+            }
+            Self::IntegerArray(a) => {
+                query.bind::<<IntegerArray as Sql>::T, _>(a.get_inner().clone())
+            }
+            Self::BigIntArray(a) => query.bind::<<BigIntArray as Sql>::T, _>(a.get_inner().clone()),
+            Self::DoubleArray(a) => query.bind::<<DoubleArray as Sql>::T, _>(a.get_inner().clone()),
+            Self::NumericArray(a) => {
+                query.bind::<<NumericArray as Sql>::T, _>(a.get_inner().clone())
+            }
+            Self::TextArray(a) => query.bind::<<TextArray as Sql>::T, _>(a.get_inner().clone()),
+            Self::BinaryArray(a) => query.bind::<<BinaryArray as Sql>::T, _>(a.get_inner().clone()),
+            Self::Null(n) => match &n.column_type {
+                Some(column_type) => bind_null(query, column_type),
+                None => {
+                    return Err(DBError::InvalidColumnDataType(
+                        "the type of a null value can not be inferred".to_string(),
+                    ))
+                }
+            },
+        })
     }
 
     /// Given a [`ColumnType`] and a value of type [`String`], it tries to parse
@@ -336,25 +984,28 @@ impl ColumnValue {
                 inner: value.clone(),
             }),
             ColumnType::Bit => ColumnValue::Bit(crate::sql_types::Bit {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::Binary => ColumnValue::Binary(crate::sql_types::Binary {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::Blob => ColumnValue::Blob(crate::sql_types::Blob {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::TinyBlob => ColumnValue::TinyBlob(crate::sql_types::TinyBlob {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::MediumBlob => ColumnValue::MediumBlob(crate::sql_types::MediumBlob {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::LongBlob => ColumnValue::LongBlob(crate::sql_types::LongBlob {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::Varbinary => ColumnValue::Varbinary(crate::sql_types::Varbinary {
-                inner: value.as_bytes().to_vec(),
+                inner: parse_bytes(&value)?,
+            }),
+            ColumnType::Bytea => ColumnValue::Bytea(crate::sql_types::Bytea {
+                inner: parse_bytes(&value)?,
             }),
             ColumnType::Date => ColumnValue::Date(crate::sql_types::Date {
                 inner: value
@@ -367,11 +1018,216 @@ impl ColumnValue {
                     .map_err(|_| DBError::FailedParseString(value.clone()))?,
             }),
             ColumnType::Timestamp => ColumnValue::Timestamp(crate::sql_types::Timestamp {
-                inner: NaiveDateTime::parse_from_str(value.as_str(), "%Y-%m-%d %H:%M:%S")
+                inner: parse_timestamp(&value)?,
+            }),
+            ColumnType::Timestamptz => ColumnValue::Timestamptz(crate::sql_types::Timestamptz {
+                inner: parse_timestamptz(&value)?,
+            }),
+            ColumnType::Interval => ColumnValue::Interval(crate::sql_types::Interval {
+                inner: pg_interval::Interval::from_postgres(&value)
+                    .map_err(|_| DBError::FailedParseString(value.clone()))?,
+            }),
+            ColumnType::Uuid => ColumnValue::Uuid(crate::sql_types::Uuid {
+                inner: value
+                    .parse()
+                    .map_err(|_| DBError::FailedParseString(value.clone()))?,
+            }),
+            ColumnType::Inet => ColumnValue::Inet(crate::sql_types::Inet {
+                inner: value
+                    .parse()
+                    .map_err(|_| DBError::FailedParseString(value.clone()))?,
+            }),
+            ColumnType::Json => ColumnValue::Json(crate::sql_types::Json {
+                inner: serde_json::from_str(&value)
+                    .map_err(|_| DBError::FailedParseString(value.clone()))?,
+            }),
+            ColumnType::Jsonb => ColumnValue::Jsonb(crate::sql_types::Jsonb {
+                inner: serde_json::from_str(&value)
                     .map_err(|_| DBError::FailedParseString(value.clone()))?,
             }),
-            ColumnType::Interval => panic!("Not implemented!"),
+            ColumnType::BoolArray
+            | ColumnType::SmallIntArray
+            | ColumnType::IntegerArray
+            | ColumnType::BigIntArray
+            | ColumnType::DoubleArray
+            | ColumnType::NumericArray
+            | ColumnType::TextArray
+            | ColumnType::BinaryArray => {
+                let element_type = sql_type
+                    .element_type()
+                    .expect("Array types have an element type");
+                let elements = parse_array_literal(&value)?
+                    .into_iter()
+                    .map(|v| Self::parse_type(element_type.clone(), v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::new_array_of(&sql_type, elements)?
+            }
+        })
+    }
+}
+
+/// Binds a `NULL` value of type `column_type` to the next placeholder of a boxed
+/// SQL query, as PostgreSQL does not infer the type of bound parameters.
+fn bind_null<'f>(
+    query: BoxedSqlQuery<'f, Pg, SqlQuery>,
+    column_type: &ColumnType,
+) -> BoxedSqlQuery<'f, Pg, SqlQuery> {
+    macro_rules! null {
+        ($typ:ty) => {
+            query.bind::<diesel::sql_types::Nullable<<$typ as Sql>::T>, _>(
+                None::<<$typ as Sql>::Inner>,
+            )
+        };
+    }
+
+    match column_type {
+        ColumnType::Bool => null!(Bool),
+        ColumnType::SmallInt | ColumnType::Int2 => null!(SmallInt),
+        ColumnType::Integer | ColumnType::Int4 => null!(Integer),
+        ColumnType::BigInt | ColumnType::Int8 => null!(BigInt),
+        ColumnType::Float | ColumnType::Float4 => null!(Float),
+        ColumnType::Double | ColumnType::Float8 => null!(Double),
+        ColumnType::Numeric | ColumnType::Decimal => null!(Numeric),
+        ColumnType::Text
+        | ColumnType::VarChar
+        | ColumnType::Char
+        | ColumnType::TinyText
+        | ColumnType::MediumText
+        | ColumnType::LongText => null!(Text),
+        ColumnType::Binary
+        | ColumnType::TinyBlob
+        | ColumnType::Blob
+        | ColumnType::MediumBlob
+        | ColumnType::LongBlob
+        | ColumnType::Varbinary
+        | ColumnType::Bit
+        | ColumnType::Bytea => null!(Binary),
+        ColumnType::Date => null!(Date),
+        ColumnType::Time => null!(Time),
+        ColumnType::Timestamp => null!(Timestamp),
+        ColumnType::Timestamptz => null!(Timestamptz),
+        ColumnType::Interval => {
+            query.bind::<diesel::sql_types::Nullable<<Interval as Sql>::T>, _>(None::<PgInterval>)
+        }
+        ColumnType::Uuid => null!(Uuid),
+        ColumnType::Inet => null!(Inet),
+        ColumnType::Json => null!(Json),
+        ColumnType::Jsonb => null!(Jsonb),
+        ColumnType::BoolArray => null!(BoolArray),
+        ColumnType::SmallIntArray => null!(SmallIntArray),
+        ColumnType::IntegerArray => null!(IntegerArray),
+        ColumnType::BigIntArray => null!(BigIntArray),
+        ColumnType::DoubleArray => null!(DoubleArray),
+        ColumnType::NumericArray => null!(NumericArray),
+        ColumnType::TextArray => null!(TextArray),
+        ColumnType::BinaryArray => null!(BinaryArray),
+    }
+}
+
+/// Parses a timestamp without time zone, either in PostgreSQL format with optional fractional
+/// seconds (e.g. `2016-07-08 09:10:11.123`) or in RFC 3339. Offsets of RFC 3339 timestamps are
+/// applied, so that the timestamp is stored in UTC.
+fn parse_timestamp(value: &str) -> Result<NaiveDateTime, DBError> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f"))
+        .or_else(|_| DateTime::parse_from_rfc3339(value).map(|t| t.naive_utc()))
+        .map_err(|_| DBError::FailedParseString(value.to_string()))
+}
+
+/// Parses a timestamp with time zone, either in RFC 3339 or in PostgreSQL format
+/// (e.g. `2016-07-08 09:10:11+02`). Timestamps without time zone are taken as UTC.
+fn parse_timestamptz(value: &str) -> Result<DateTime<Utc>, DBError> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%#z"))
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+                .map(|t| Utc.from_utc_datetime(&t))
         })
+        .map_err(|_| DBError::FailedParseString(value.to_string()))
+}
+
+/// Formats bytes as a PostgreSQL `bytea` literal, in hex format.
+fn to_bytea_literal(bytes: &[u8]) -> String {
+    format!("'\\x{}'", hex::encode(bytes))
+}
+
+/// Escapes backslashes and row/column delimiters, as expected by
+/// `COPY ... FROM STDIN` in text format.
+fn escape_copy_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parses the elements of a one-dimensional PostgreSQL array literal,
+/// e.g. `{1,2}` or `{"a","b"}`.
+fn parse_array_literal(value: &str) -> Result<Vec<String>, DBError> {
+    let failed = || DBError::FailedParseString(value.to_string());
+    let inner = value
+        .trim()
+        .strip_prefix('{')
+        .and_then(|v| v.strip_suffix('}'))
+        .ok_or_else(failed)?;
+
+    let mut elements = Vec::new();
+    if inner.trim().is_empty() {
+        return Ok(elements);
+    }
+    let (mut element, mut quoted, mut was_quoted) = (String::new(), false, false);
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => element.push(chars.next().ok_or_else(failed)?),
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            ',' if !quoted => {
+                let element = std::mem::take(&mut element);
+                elements.push(if was_quoted {
+                    element
+                } else {
+                    element.trim().to_string()
+                });
+                was_quoted = false;
+            }
+            // multi-dimensional arrays are not supported
+            '{' | '}' if !quoted => return Err(failed()),
+            _ => element.push(c),
+        }
+    }
+    if quoted {
+        return Err(failed());
+    }
+    elements.push(if was_quoted {
+        element
+    } else {
+        element.trim().to_string()
+    });
+
+    Ok(elements)
+}
+
+/// Parses bytes from a string. Hex encoded strings, prefixed by either
+/// `0x` or `\x`, are decoded, otherwise the raw bytes of the string are used.
+fn parse_bytes(value: &str) -> Result<Vec<u8>, DBError> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("\\x"))
+    {
+        Some(hex_value) => {
+            hex::decode(hex_value).map_err(|_| DBError::FailedParseString(value.to_string()))
+        }
+        None => Ok(value.as_bytes().to_vec()),
     }
 }
 
@@ -404,10 +1260,118 @@ pub enum ColumnType {
     LongBlob,
     Varbinary,
     Bit,
+    Bytea,
     Date,
     Interval,
     Time,
     Timestamp,
+    Timestamptz,
+    Json,
+    Jsonb,
+    Uuid,
+    Inet,
+    BoolArray,
+    SmallIntArray,
+    IntegerArray,
+    BigIntArray,
+    DoubleArray,
+    NumericArray,
+    TextArray,
+    BinaryArray,
+}
+
+impl ColumnType {
+    /// Maps a column type, as reported by PostgreSQL `information_schema.columns`,
+    /// to a [`ColumnType`]. The `data_type` is used, unless it does not
+    /// identify the underlying type (arrays and user-defined types), in which case
+    /// we fall back to the `udt_name`.
+    pub fn from_information_schema(data_type: &str, udt_name: &str) -> Result<Self, DBError> {
+        match data_type {
+            "ARRAY" | "USER-DEFINED" => Self::try_from(udt_name),
+            _ => Self::try_from(data_type),
+        }
+    }
+
+    /// Gets the array type whose elements are of type `element_type`, if supported.
+    pub fn array_of(element_type: &ColumnType) -> Option<Self> {
+        Some(match element_type {
+            Self::Bool => Self::BoolArray,
+            Self::SmallInt | Self::Int2 => Self::SmallIntArray,
+            Self::Integer | Self::Int4 => Self::IntegerArray,
+            Self::BigInt | Self::Int8 => Self::BigIntArray,
+            Self::Float | Self::Float4 | Self::Double | Self::Float8 => Self::DoubleArray,
+            Self::Numeric | Self::Decimal => Self::NumericArray,
+            Self::Text
+            | Self::VarChar
+            | Self::Char
+            | Self::TinyText
+            | Self::MediumText
+            | Self::LongText => Self::TextArray,
+            Self::Binary
+            | Self::TinyBlob
+            | Self::Blob
+            | Self::MediumBlob
+            | Self::LongBlob
+            | Self::Varbinary
+            | Self::Bit
+            | Self::Bytea => Self::BinaryArray,
+            _ => return None,
+        })
+    }
+
+    /// Gets the PostgreSQL name of the type, as used to declare a column of this type.
+    pub fn sql_type_name(&self) -> String {
+        if let Some(element_type) = self.element_type() {
+            return format!("{}[]", element_type.sql_type_name());
+        }
+        let name = match self {
+            Self::Bool => "boolean",
+            Self::SmallInt | Self::Int2 => "smallint",
+            Self::Integer | Self::Int4 => "integer",
+            Self::BigInt | Self::Int8 => "bigint",
+            Self::Float | Self::Float4 => "real",
+            Self::Double | Self::Float8 => "double precision",
+            Self::Numeric | Self::Decimal => "numeric",
+            Self::Text | Self::TinyText | Self::MediumText | Self::LongText => "text",
+            Self::VarChar => "varchar",
+            Self::Char => "char",
+            Self::Binary
+            | Self::TinyBlob
+            | Self::Blob
+            | Self::MediumBlob
+            | Self::LongBlob
+            | Self::Varbinary
+            | Self::Bytea => "bytea",
+            Self::Bit => "varbit",
+            Self::Date => "date",
+            Self::Interval => "interval",
+            Self::Time => "time",
+            Self::Timestamp => "timestamp",
+            Self::Timestamptz => "timestamptz",
+            Self::Json => "json",
+            Self::Jsonb => "jsonb",
+            Self::Uuid => "uuid",
+            Self::Inet => "inet",
+            // array types are handled above
+            _ => unreachable!(),
+        };
+        name.to_string()
+    }
+
+    /// Gets the type of the elements of an array type, or `None` for non-array types.
+    pub fn element_type(&self) -> Option<Self> {
+        Some(match self {
+            Self::BoolArray => Self::Bool,
+            Self::SmallIntArray => Self::SmallInt,
+            Self::IntegerArray => Self::Integer,
+            Self::BigIntArray => Self::BigInt,
+            Self::DoubleArray => Self::Double,
+            Self::NumericArray => Self::Numeric,
+            Self::TextArray => Self::Text,
+            Self::BinaryArray => Self::Binary,
+            _ => return None,
+        })
+    }
 }
 
 impl TryFrom<&str> for ColumnType {
@@ -415,8 +1379,15 @@ impl TryFrom<&str> for ColumnType {
 
     fn try_from(value: &str) -> Result<Self, DBError> {
         let value = value.to_lowercase();
+        // array types, either as `text[]` or as `_text`, the latter being their `udt_name`
+        if let Some(element) = value.strip_suffix("[]").or_else(|| value.strip_prefix('_')) {
+            return Self::try_from(element)
+                .ok()
+                .and_then(|element_type| Self::array_of(&element_type))
+                .ok_or(DBError::InvalidFieldType(value));
+        }
         let sql_type = match value.as_str() {
-            "bool" => Self::Bool,
+            "bool" | "boolean" => Self::Bool,
             "smallint" => Self::SmallInt,
             "int2" => Self::Int2,
             "integer" => Self::Integer,
@@ -424,14 +1395,14 @@ impl TryFrom<&str> for ColumnType {
             "bigint" => Self::BigInt,
             "int8" => Self::Int8,
             "float" => Self::Float,
-            "float4" => Self::Float4,
+            "float4" | "real" => Self::Float4,
             "double" => Self::Double,
-            "float8" => Self::Float8,
+            "float8" | "double precision" => Self::Float8,
             "numeric" => Self::Numeric,
             "decimal" => Self::Decimal,
             "text" => Self::Text,
-            "varchar" => Self::VarChar,
-            "char" => Self::Char,
+            "varchar" | "character varying" => Self::VarChar,
+            "char" | "bpchar" | "character" => Self::Char,
             "tinytext" => Self::TinyText,
             "mediumtext" => Self::MediumText,
             "longtext" => Self::LongText,
@@ -441,12 +1412,19 @@ impl TryFrom<&str> for ColumnType {
             "mediumblob" => Self::MediumBlob,
             "longblob" => Self::LongBlob,
             "varbinary" => Self::Varbinary,
-            "bit" => Self::Bit,
+            // bit strings are not supported, as values are bound as bytes,
+            // which PostgreSQL does not convert into bit strings
+            "bytea" => Self::Bytea,
             "date" => Self::Date,
             "interval" => Self::Interval,
-            "time" => Self::Time,
-            "timestamp" => Self::Timestamp,
-            _ => return Err(DBError::InvalidFieldType),
+            "time" | "time without time zone" => Self::Time,
+            "timestamp" | "timestamp without time zone" => Self::Timestamp,
+            "timestamptz" | "timestamp with time zone" => Self::Timestamptz,
+            "json" => Self::Json,
+            "jsonb" => Self::Jsonb,
+            "uuid" => Self::Uuid,
+            "inet" => Self::Inet,
+            _ => return Err(DBError::InvalidFieldType(value)),
         };
 
         Ok(sql_type)
@@ -537,50 +1515,53 @@ mod tests {
         let sql_binary = ColumnValue::Binary(Binary {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_binary.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_binary.to_string(), "'\\x000102'".to_string());
 
         let sql_tiny_blob = ColumnValue::TinyBlob(TinyBlob {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_tiny_blob.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_tiny_blob.to_string(), "'\\x000102'".to_string());
 
         let sql_blob = ColumnValue::Blob(Blob {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_blob.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_blob.to_string(), "'\\x000102'".to_string());
 
         let sql_medium_blob = ColumnValue::MediumBlob(MediumBlob {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_medium_blob.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_medium_blob.to_string(), "'\\x000102'".to_string());
 
         let sql_long_blob = ColumnValue::LongBlob(LongBlob {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_long_blob.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_long_blob.to_string(), "'\\x000102'".to_string());
 
         let sql_var_binary = ColumnValue::Varbinary(Varbinary {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_var_binary.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_var_binary.to_string(), "'\\x000102'".to_string());
 
         let sql_bit = ColumnValue::Bit(Bit {
             inner: vec![0u8, 1, 2],
         });
-        assert_eq!(sql_bit.to_string(), "[0, 1, 2]".to_string());
+        assert_eq!(sql_bit.to_string(), "'\\x000102'".to_string());
+
+        let sql_bytea = ColumnValue::Bytea(Bytea {
+            inner: vec![0u8, 1, 2],
+        });
+        assert_eq!(sql_bytea.to_string(), "'\\x000102'".to_string());
 
         let sql_date = ColumnValue::Date(Date {
             inner: NaiveDate::from_ymd_opt(2023, 2, 22).unwrap(),
         });
         assert_eq!(sql_date.to_string(), "'2023-02-22'");
 
-        // let sql_interval = ColumnValue::Interval(Interval {
-        //     pub inner: pg_interval::Interval::from_postgres("1 years 1 months 1 days 1 hours").unwrap(),
-        // });
-        // assert_eq!(
-        //     sql_interval.to_string(),
-        //     "interval '1 years 1 months 1 days 1 hours'"
-        // );
+        let sql_interval = ColumnValue::Interval(Interval {
+            inner: pg_interval::Interval::from_postgres("1 years 1 months 1 days 1 hours").unwrap(),
+        });
+        assert_eq!(sql_interval.to_string(), "interval 'P1Y1M1DT1H'");
+        assert_eq!(sql_interval.to_copy_text().unwrap(), "P1Y1M1DT1H");
 
         let sql_time = ColumnValue::Time(Time {
             inner: NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
@@ -596,6 +1577,46 @@ mod tests {
         assert_eq!(sql_timestamp.to_string(), "'2016-07-08 09:10:11'");
     }
 
+    #[test]
+    fn sql_type_to_copy_text() {
+        let sql_bool = ColumnValue::Bool(Bool { inner: false });
+        assert_eq!(sql_bool.to_copy_text().unwrap(), "f".to_string());
+
+        let sql_big_int = ColumnValue::BigInt(BigInt { inner: -1_i64 });
+        assert_eq!(sql_big_int.to_copy_text().unwrap(), "-1".to_string());
+
+        let sql_numeric = ColumnValue::Numeric(Numeric {
+            inner: BigDecimal::from_str("3.1415").unwrap(),
+        });
+        assert_eq!(sql_numeric.to_copy_text().unwrap(), "3.1415".to_string());
+
+        let sql_text = ColumnValue::Text(Text {
+            inner: "it's\ta\\b\n".to_string(),
+        });
+        assert_eq!(
+            sql_text.to_copy_text().unwrap(),
+            "it's\\ta\\\\b\\n".to_string()
+        );
+
+        let sql_bytea = ColumnValue::Bytea(Bytea {
+            inner: vec![0u8, 1, 2],
+        });
+        assert_eq!(sql_bytea.to_copy_text().unwrap(), "\\\\x000102".to_string());
+
+        let sql_date = ColumnValue::Date(Date {
+            inner: NaiveDate::from_ymd_opt(2023, 2, 22).unwrap(),
+        });
+        assert_eq!(sql_date.to_copy_text().unwrap(), "2023-02-22");
+
+        let sql_timestamp = ColumnValue::Timestamp(Timestamp {
+            inner: NaiveDate::from_ymd_opt(2016, 7, 8)
+                .unwrap()
+                .and_hms_opt(9, 10, 11)
+                .unwrap(),
+        });
+        assert_eq!(sql_timestamp.to_copy_text().unwrap(), "2016-07-08 09:10:11");
+    }
+
     #[test]
     fn test_sql_type_map_from_string() {
         assert_eq!(ColumnType::try_from("bool").unwrap(), ColumnType::Bool);
@@ -662,7 +1683,11 @@ mod tests {
             ColumnType::try_from("varbinary").unwrap(),
             ColumnType::Varbinary
         );
-        assert_eq!(ColumnType::try_from("bit").unwrap(), ColumnType::Bit);
+        assert!(matches!(
+            ColumnType::try_from("bit"),
+            Err(DBError::InvalidFieldType(t)) if t == "bit"
+        ));
+        assert_eq!(ColumnType::try_from("bytea").unwrap(), ColumnType::Bytea);
         assert_eq!(ColumnType::try_from("date").unwrap(), ColumnType::Date);
         assert_eq!(
             ColumnType::try_from("interval").unwrap(),
@@ -675,6 +1700,313 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sql_type_map_from_information_schema() {
+        let columns = [
+            ("boolean", "bool", ColumnType::Bool),
+            ("smallint", "int2", ColumnType::SmallInt),
+            ("integer", "int4", ColumnType::Integer),
+            ("bigint", "int8", ColumnType::BigInt),
+            ("real", "float4", ColumnType::Float4),
+            ("double precision", "float8", ColumnType::Float8),
+            ("numeric", "numeric", ColumnType::Numeric),
+            ("text", "text", ColumnType::Text),
+            ("character varying", "varchar", ColumnType::VarChar),
+            ("character", "bpchar", ColumnType::Char),
+            ("bytea", "bytea", ColumnType::Bytea),
+            ("date", "date", ColumnType::Date),
+            ("interval", "interval", ColumnType::Interval),
+            ("time without time zone", "time", ColumnType::Time),
+            (
+                "timestamp without time zone",
+                "timestamp",
+                ColumnType::Timestamp,
+            ),
+            (
+                "timestamp with time zone",
+                "timestamptz",
+                ColumnType::Timestamptz,
+            ),
+            ("json", "json", ColumnType::Json),
+            ("jsonb", "jsonb", ColumnType::Jsonb),
+            ("uuid", "uuid", ColumnType::Uuid),
+            ("inet", "inet", ColumnType::Inet),
+            ("ARRAY", "_text", ColumnType::TextArray),
+            ("ARRAY", "_int8", ColumnType::BigIntArray),
+            ("ARRAY", "_numeric", ColumnType::NumericArray),
+            ("ARRAY", "_bytea", ColumnType::BinaryArray),
+        ];
+        for (data_type, udt_name, column_type) in columns {
+            assert_eq!(
+                ColumnType::from_information_schema(data_type, udt_name).unwrap(),
+                column_type
+            );
+        }
+
+        assert!(matches!(
+            ColumnType::from_information_schema("USER-DEFINED", "my_enum"),
+            Err(DBError::InvalidFieldType(t)) if t == "my_enum"
+        ));
+        assert!(ColumnType::from_information_schema("point", "point").is_err());
+        assert!(ColumnType::from_information_schema("bit varying", "varbit").is_err());
+        assert!(ColumnType::from_information_schema("ARRAY", "_uuid").is_err());
+    }
+
+    #[test]
+    fn test_sql_type_name() {
+        assert_eq!(ColumnType::Int8.sql_type_name(), "bigint");
+        assert_eq!(ColumnType::Float8.sql_type_name(), "double precision");
+        assert_eq!(ColumnType::Binary.sql_type_name(), "bytea");
+        assert_eq!(ColumnType::BigIntArray.sql_type_name(), "bigint[]");
+        assert_eq!(ColumnType::BinaryArray.sql_type_name(), "bytea[]");
+
+        // the name maps back to an equivalent type
+        for name in [
+            "boolean",
+            "smallint",
+            "integer",
+            "bigint",
+            "real",
+            "double precision",
+            "numeric",
+            "text",
+            "varchar",
+            "char",
+            "bytea",
+            "date",
+            "interval",
+            "time",
+            "timestamp",
+            "timestamptz",
+            "json",
+            "jsonb",
+            "uuid",
+            "inet",
+            "text[]",
+            "numeric[]",
+        ] {
+            let column_type = ColumnType::try_from(name).unwrap();
+            assert_eq!(column_type.sql_type_name(), name);
+        }
+    }
+
+    #[test]
+    fn it_works_array_values() {
+        let text_array = ColumnValue::new_array(vec![
+            ColumnValue::Text(Text::set_inner("a\"b".to_string())),
+            ColumnValue::Text(Text::set_inner("c,d".to_string())),
+        ])
+        .unwrap();
+        assert_eq!(
+            text_array,
+            ColumnValue::TextArray(TextArray {
+                inner: vec!["a\"b".to_string(), "c,d".to_string()]
+            })
+        );
+        assert_eq!(text_array.to_string(), "'{\"a\\\"b\",\"c,d\"}'");
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::TextArray, "{\"a\\\"b\",\"c,d\"}".to_string())
+                .unwrap(),
+            text_array
+        );
+
+        let numeric_array =
+            ColumnValue::parse_type(ColumnType::NumericArray, "{1.5, 2}".to_string()).unwrap();
+        assert_eq!(
+            numeric_array,
+            ColumnValue::NumericArray(NumericArray {
+                inner: vec![
+                    BigDecimal::from_str("1.5").unwrap(),
+                    BigDecimal::from_str("2").unwrap()
+                ]
+            })
+        );
+        assert_eq!(numeric_array.to_copy_text().unwrap(), "{1.5,2}");
+
+        let binary_array =
+            ColumnValue::new_array(vec![ColumnValue::Bytea(Bytea::set_inner(vec![0u8, 1]))])
+                .unwrap();
+        assert_eq!(binary_array.to_copy_text().unwrap(), "{\"\\\\\\\\x0001\"}");
+
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::BigIntArray, "{}".to_string()).unwrap(),
+            ColumnValue::BigIntArray(BigIntArray { inner: vec![] })
+        );
+        assert!(ColumnValue::new_array(vec![]).is_err());
+        assert!(ColumnValue::new_array(vec![
+            ColumnValue::BigInt(BigInt::set_inner(1)),
+            ColumnValue::Text(Text::set_inner("a".to_string())),
+        ])
+        .is_err());
+        assert!(ColumnValue::parse_type(ColumnType::BigIntArray, "{{1},{2}}".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_works_numeric_values() {
+        let amount =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        assert_eq!(
+            ColumnValue::parse_numeric(amount, &ColumnType::Numeric).unwrap(),
+            ColumnValue::Numeric(Numeric {
+                inner: BigDecimal::from_str(amount).unwrap()
+            })
+        );
+        assert!(matches!(
+            ColumnValue::parse_numeric(amount, &ColumnType::BigInt),
+            Err(DBError::ValueOutOfRange { .. })
+        ));
+
+        let max = BigDecimal::from(u64::MAX);
+        assert!(matches!(
+            ColumnValue::from_numeric(max.clone(), &ColumnType::Int8),
+            Err(DBError::ValueOutOfRange { .. })
+        ));
+        assert_eq!(
+            ColumnValue::from_numeric(max, &ColumnType::Text).unwrap(),
+            ColumnValue::Text(Text {
+                inner: u64::MAX.to_string()
+            })
+        );
+        assert_eq!(
+            ColumnValue::from_numeric(BigDecimal::from(i32::MAX), &ColumnType::BigInt).unwrap(),
+            ColumnValue::BigInt(BigInt {
+                inner: i64::from(i32::MAX)
+            })
+        );
+        assert!(matches!(
+            ColumnValue::from_numeric(BigDecimal::from(u32::MAX), &ColumnType::Integer),
+            Err(DBError::ValueOutOfRange { .. })
+        ));
+        assert!(matches!(
+            ColumnValue::parse_numeric("1.5", &ColumnType::SmallInt),
+            Err(DBError::ValueOutOfRange { .. })
+        ));
+        assert_eq!(
+            ColumnValue::parse_numeric("1.0", &ColumnType::SmallInt).unwrap(),
+            ColumnValue::SmallInt(SmallInt { inner: 1 })
+        );
+        assert!(ColumnValue::parse_numeric("1e", &ColumnType::Numeric).is_err());
+        assert!(ColumnValue::from_numeric(BigDecimal::from(1), &ColumnType::Bool).is_err());
+    }
+
+    #[test]
+    fn it_works_coerce_values() {
+        assert_eq!(
+            ColumnValue::BigInt(BigInt { inner: 7 })
+                .coerce(&ColumnType::Integer)
+                .unwrap(),
+            ColumnValue::Integer(Integer { inner: 7 })
+        );
+        assert!(matches!(
+            ColumnValue::BigInt(BigInt { inner: i64::MAX }).coerce(&ColumnType::Integer),
+            Err(DBError::ValueOutOfRange { .. })
+        ));
+        assert_eq!(
+            ColumnValue::Integer(Integer { inner: 7 })
+                .coerce(&ColumnType::Numeric)
+                .unwrap(),
+            ColumnValue::Numeric(Numeric {
+                inner: BigDecimal::from(7)
+            })
+        );
+        assert_eq!(
+            ColumnValue::Text(Text {
+                inner: "2016-07-08 09:10:11".to_string()
+            })
+            .coerce(&ColumnType::Timestamp)
+            .unwrap(),
+            ColumnValue::Timestamp(Timestamp {
+                inner: NaiveDate::from_ymd_opt(2016, 7, 8)
+                    .unwrap()
+                    .and_hms_opt(9, 10, 11)
+                    .unwrap(),
+            })
+        );
+        assert!(ColumnValue::Text(Text {
+            inner: "yesterday".to_string()
+        })
+        .coerce(&ColumnType::Timestamp)
+        .is_err());
+        assert_eq!(
+            ColumnValue::Binary(Binary {
+                inner: vec![0u8, 1]
+            })
+            .coerce(&ColumnType::Bytea)
+            .unwrap(),
+            ColumnValue::Bytea(Bytea {
+                inner: vec![0u8, 1]
+            })
+        );
+        assert!(ColumnValue::Bool(Bool { inner: true })
+            .coerce(&ColumnType::Integer)
+            .is_err());
+        assert_eq!(
+            ColumnValue::Float(Float { inner: 1.5 })
+                .coerce(&ColumnType::Numeric)
+                .unwrap(),
+            ColumnValue::Numeric(Numeric {
+                inner: BigDecimal::from_str("1.5").unwrap()
+            })
+        );
+        assert_eq!(
+            ColumnValue::IntegerArray(IntegerArray { inner: vec![1, 2] })
+                .coerce(&ColumnType::NumericArray)
+                .unwrap(),
+            ColumnValue::NumericArray(NumericArray {
+                inner: vec![BigDecimal::from(1), BigDecimal::from(2)]
+            })
+        );
+        assert!(ColumnValue::Integer(Integer { inner: 1 })
+            .coerce(&ColumnType::IntegerArray)
+            .is_err());
+    }
+
+    #[test]
+    fn it_works_null_values() {
+        let null = ColumnValue::Null(Null::default());
+        assert_eq!(null.to_string(), "NULL");
+        assert_eq!(null.to_copy_text().unwrap(), "\\N");
+        assert!(null
+            .bind(diesel::sql_query("SELECT $1").into_boxed())
+            .is_err());
+
+        let null = null.coerce(&ColumnType::Numeric).unwrap();
+        assert_eq!(
+            null,
+            ColumnValue::Null(Null {
+                column_type: Some(ColumnType::Numeric)
+            })
+        );
+        assert!(null
+            .bind(diesel::sql_query("SELECT $1").into_boxed())
+            .is_ok());
+        assert!(ColumnValue::new_array(vec![ColumnValue::Null(Null::default())]).is_err());
+    }
+
+    #[test]
+    fn it_works_json_values() {
+        let raw = r#"{"name": "it's", "traits": [{"value": "a\tb"}]}"#.to_string();
+        let jsonb = ColumnValue::parse_type(ColumnType::Jsonb, raw.clone()).unwrap();
+        assert_eq!(
+            jsonb,
+            ColumnValue::Jsonb(Jsonb {
+                inner: serde_json::json!({"name": "it's", "traits": [{"value": "a\tb"}]})
+            })
+        );
+        assert_eq!(
+            jsonb.to_string(),
+            r#"'{"name":"it's","traits":[{"value":"a\tb"}]}'"#
+        );
+        assert_eq!(
+            jsonb.to_copy_text().unwrap(),
+            r#"{"name":"it's","traits":[{"value":"a\\tb"}]}"#
+        );
+
+        let json = ColumnValue::parse_type(ColumnType::Json, "[1, null]".to_string()).unwrap();
+        assert_eq!(json.to_string(), "'[1,null]'");
+        assert!(ColumnValue::parse_type(ColumnType::Jsonb, "{\"a\":".to_string()).is_err());
+    }
+
     #[test]
     fn it_works_parse_sql_types() {
         let x = "10".to_string();
@@ -781,6 +2113,29 @@ mod tests {
             })
         );
 
+        let x = "0x000102".to_string();
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::Bytea, x).unwrap(),
+            ColumnValue::Bytea(Bytea {
+                inner: vec![0u8, 1, 2]
+            })
+        );
+        let x = "\\x000102".to_string();
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::Binary, x).unwrap(),
+            ColumnValue::Binary(Binary {
+                inner: vec![0u8, 1, 2]
+            })
+        );
+        let x = "abc".to_string();
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::Blob, x).unwrap(),
+            ColumnValue::Blob(Blob {
+                inner: vec![97u8, 98, 99]
+            })
+        );
+        assert!(ColumnValue::parse_type(ColumnType::Bytea, "0xzz".to_string()).is_err());
+
         let x = "2023-01-01".to_string();
         assert_eq!(
             ColumnValue::parse_type(ColumnType::Date, x).unwrap(),
@@ -805,5 +2160,67 @@ mod tests {
                     .unwrap()
             })
         );
+
+        let fractional = Timestamp {
+            inner: NaiveDate::from_ymd_opt(2023, 1, 1)
+                .unwrap()
+                .and_hms_milli_opt(23, 59, 59, 123)
+                .unwrap(),
+        };
+        for x in [
+            "2023-01-01 23:59:59.123",
+            "2023-01-01T23:59:59.123",
+            "2023-01-01T23:59:59.123Z",
+            "2023-01-02T01:59:59.123+02:00",
+        ] {
+            assert_eq!(
+                ColumnValue::parse_type(ColumnType::Timestamp, x.to_string()).unwrap(),
+                ColumnValue::Timestamp(fractional.clone())
+            );
+        }
+        assert!(ColumnValue::parse_type(ColumnType::Timestamp, "yesterday".to_string()).is_err());
+
+        let utc = Timestamptz {
+            inner: DateTime::parse_from_rfc3339("2023-01-01T21:59:59Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        };
+        for x in [
+            "2023-01-01T23:59:59+02:00",
+            "2023-01-01 23:59:59+02",
+            "2023-01-01 21:59:59",
+        ] {
+            assert_eq!(
+                ColumnValue::parse_type(ColumnType::Timestamptz, x.to_string()).unwrap(),
+                ColumnValue::Timestamptz(utc.clone())
+            );
+        }
+        assert_eq!(
+            ColumnValue::Timestamptz(utc).to_string(),
+            "'2023-01-01T21:59:59+00:00'"
+        );
+
+        let x = "1 years 1 months 1 days 1 hours".to_string();
+        assert_eq!(
+            ColumnValue::parse_type(ColumnType::Interval, x).unwrap(),
+            ColumnValue::Interval(Interval {
+                inner: pg_interval::Interval::new(13, 1, 3_600_000_000)
+            })
+        );
+
+        let x = "67e55044-10b1-426f-9247-bb680e5fe0c8".to_string();
+        let sql_uuid = ColumnValue::parse_type(ColumnType::Uuid, x.clone()).unwrap();
+        assert_eq!(sql_uuid.to_string(), format!("'{}'", x));
+        assert!(ColumnValue::parse_type(ColumnType::Uuid, "67e55044".to_string()).is_err());
+
+        for (x, inet) in [
+            ("10.0.0.1", "10.0.0.1/32"),
+            ("10.0.0.0/8", "10.0.0.0/8"),
+            ("::1", "::1/128"),
+        ] {
+            let sql_inet = ColumnValue::parse_type(ColumnType::Inet, x.to_string()).unwrap();
+            assert_eq!(sql_inet.to_copy_text().unwrap(), inet);
+        }
+        assert!(ColumnValue::parse_type(ColumnType::Inet, "10.0.0.256".to_string()).is_err());
     }
 }
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use diesel_async::{scoped_futures::ScopedFutureExt, SimpleAsyncConnection};
+
+use crate::{db_loader::DBLoader, error::DBError, sql_types::ColumnType};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Definition of a table, from which the table is created, or altered to add
+/// its missing columns.
+pub struct TableDefinition {
+    /// Name of the table.
+    name: String,
+    /// Column names and types, in declaration order.
+    columns: Vec<(String, ColumnType)>,
+    /// Primary key column names, in the order of the primary key.
+    primary_key: Vec<String>,
+}
+
+impl TableDefinition {
+    /// Creates a new table definition, with the primary key columns `primary_key`.
+    pub fn new(name: String, primary_key: Vec<(String, ColumnType)>) -> Self {
+        Self {
+            name,
+            primary_key: primary_key.iter().map(|(c, _)| c.clone()).collect(),
+            columns: primary_key,
+        }
+    }
+
+    /// Adds a column to the table, unless a column with the same name is already defined.
+    pub fn add_column(&mut self, name: String, column_type: ColumnType) {
+        if self.column_type(&name).is_none() {
+            self.columns.push((name, column_type));
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn columns(&self) -> &[(String, ColumnType)] {
+        &self.columns
+    }
+
+    pub fn primary_key(&self) -> &[String] {
+        &self.primary_key
+    }
+
+    /// Gets the type of a column, if defined.
+    pub fn column_type(&self, name: &str) -> Option<&ColumnType> {
+        self.columns
+            .iter()
+            .find(|(c, _)| c == name)
+            .map(|(_, column_type)| column_type)
+    }
+
+    /// Builds the query creating the table in `schema`, if it does not exist.
+    pub(crate) fn create_table_query(&self, schema: &str) -> String {
+        let mut definitions = self
+            .columns
+            .iter()
+            .map(|(c, column_type)| {
+                let not_null = if self.primary_key.contains(c) {
+                    " NOT NULL"
+                } else {
+                    ""
+                };
+                format!("{} {}{}", c, column_type.sql_type_name(), not_null)
+            })
+            .collect::<Vec<_>>();
+        if !self.primary_key.is_empty() {
+            definitions.push(format!(
+                "CONSTRAINT {}_pk PRIMARY KEY ({})",
+                self.name,
+                self.primary_key.join(", ")
+            ));
+        }
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} ({})",
+            schema,
+            self.name,
+            definitions.join(", ")
+        )
+    }
+
+    /// Builds the query adding to the table in `schema` the columns which are not among
+    /// its `existing_columns`, if any. Existing columns are left as they are, even if
+    /// their type differs from the defined one.
+    pub(crate) fn add_columns_query(
+        &self,
+        schema: &str,
+        existing_columns: &HashMap<String, ColumnType>,
+    ) -> Option<String> {
+        let additions = self
+            .columns
+            .iter()
+            .filter(|(c, _)| !existing_columns.contains_key(c))
+            .map(|(c, column_type)| {
+                format!(
+                    "ADD COLUMN IF NOT EXISTS {} {}",
+                    c,
+                    column_type.sql_type_name()
+                )
+            })
+            .collect::<Vec<_>>();
+        if additions.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "ALTER TABLE {}.{} {}",
+            schema,
+            self.name,
+            additions.join(", ")
+        ))
+    }
+}
+
+/// Trait to create and alter tables through a [`DBLoader`], from their [`TableDefinition`].
+#[async_trait]
+pub trait TableLoader {
+    /// Creates the table of `definition` if it does not exist, or adds its missing columns
+    /// otherwise, and then reloads the tables metadata (see [`DBLoader::load_tables`]).
+    /// Returns whether the table was created or altered. Tables metadata is expected
+    /// to be loaded beforehand.
+    async fn ensure_table(&mut self, definition: &TableDefinition) -> Result<bool, DBError>;
+}
+
+#[async_trait]
+impl TableLoader for DBLoader {
+    async fn ensure_table(&mut self, definition: &TableDefinition) -> Result<bool, DBError> {
+        let schema = self.get_schema().clone();
+        let query = match self.get_tables().get(definition.name()) {
+            Some(existing_columns) => {
+                match definition.add_columns_query(&schema, existing_columns) {
+                    Some(query) => query,
+                    None => return Ok(false),
+                }
+            }
+            None => definition.create_table_query(&schema),
+        };
+
+        self.connection()
+            .await?
+            .build_transaction()
+            .read_write()
+            .run::<_, DBError, _>(|conn| {
+                async move {
+                    conn.batch_execute(&query)
+                        .await
+                        .map_err(|e| DBError::FailedToExecuteQuery {
+                            query: query.clone(),
+                            error: e.to_string(),
+                        })
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        // refresh the metadata of the created or altered table
+        self.load_tables().await?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition() -> TableDefinition {
+        let mut definition = TableDefinition::new(
+            "block_meta".to_string(),
+            vec![("id".to_string(), ColumnType::Text)],
+        );
+        definition.add_column("number".to_string(), ColumnType::Numeric);
+        definition.add_column("hash".to_string(), ColumnType::Text);
+        definition.add_column("tags".to_string(), ColumnType::TextArray);
+        // already defined, ignored
+        definition.add_column("id".to_string(), ColumnType::BigInt);
+        definition
+    }
+
+    #[test]
+    fn it_works_create_table_query() {
+        let definition = definition();
+        assert_eq!(definition.column_type("id"), Some(&ColumnType::Text));
+        assert_eq!(definition.primary_key(), ["id"]);
+        assert_eq!(
+            definition.create_table_query("my_schema"),
+            "CREATE TABLE IF NOT EXISTS my_schema.block_meta (id text NOT NULL, number numeric, hash text, tags text[], CONSTRAINT block_meta_pk PRIMARY KEY (id))"
+        );
+    }
+
+    #[test]
+    fn it_works_add_columns_query() {
+        let definition = definition();
+        let mut existing_columns = HashMap::from([
+            ("id".to_string(), ColumnType::Text),
+            ("number".to_string(), ColumnType::Integer),
+        ]);
+        assert_eq!(
+            definition.add_columns_query("my_schema", &existing_columns),
+            Some("ALTER TABLE my_schema.block_meta ADD COLUMN IF NOT EXISTS hash text, ADD COLUMN IF NOT EXISTS tags text[]".to_string())
+        );
+
+        existing_columns.insert("hash".to_string(), ColumnType::Text);
+        existing_columns.insert("tags".to_string(), ColumnType::TextArray);
+        assert_eq!(
+            definition.add_columns_query("my_schema", &existing_columns),
+            None
+        );
+    }
+}
@@ -88,20 +88,19 @@ pub struct Resolver {
 impl Resolver {
     /// Create a new resolver
     /// # Arguments
-    ///    * `pg_database_url` - Postgres database URL
+    ///    * `connection_pool` - A connection pool to the database
     ///    * `downloaders` - Map of downloader schemes to downloader implementations
     ///    * `max_concurrent_resolver_tasks` - Maximum number of concurrent resolver tasks
     /// # Returns
     ///   * `Resolver` - Resolver instance
     pub async fn new(
-        pg_database_url: &str,
+        connection_pool: PgPool,
         downloaders: HashMap<String, Arc<dyn LinkResolver>>,
         max_concurrent_resolver_tasks: usize,
     ) -> Result<Self> {
         let (off_chain_task_sender, off_chain_task_receiver) = bounded::<Message>(1000);
 
-        let connection_pool = PgPool::connect(pg_database_url).await?;
-        let mut state = DBResolverState::new(connection_pool.clone()).await?;
+        let mut state = DBResolverState::new(connection_pool).await?;
         Ok(Self {
             off_chain_task_receiver,
             off_chain_task_sender: off_chain_task_sender.clone(),
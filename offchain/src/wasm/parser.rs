@@ -4,7 +4,7 @@ use async_trait::async_trait;
 use futures::executor::block_on;
 use int_enum::IntEnum;
 use prost::Message;
-use sqlx::{PgPool, Postgres, QueryBuilder};
+use sqlx::{query_builder::Separated, PgPool, Postgres, QueryBuilder};
 use substreams_sink::{pb, OffchainDataContent, OffchainDataRecord, OffchainDataRecords};
 use wasmer::{
     imports, Cranelift, Function, FunctionEnv, FunctionEnvMut, Instance, Memory, Module, Store,
@@ -221,15 +221,9 @@ fn build_query<'args>(
         QueryBuilder::<Postgres>::new(format!("INSERT INTO {}.{} (", manifest, record.record));
     let mut separated = query.separated(", ");
     for field in &record.fields {
-        let typed = field
-            .new_value
-            .as_ref()
-            .ok_or(anyhow!("Failed to get typed value"))?
-            .typed
-            .to_owned()
-            .ok_or(anyhow!("Failed to access typed value"))?;
+        let typed = field.new_value.as_ref().and_then(|v| v.typed.to_owned());
         match typed {
-            pb::value::Typed::Offchaindata(_) | pb::value::Typed::Array(_) => {
+            Some(pb::value::Typed::Offchaindata(_)) => {
                 return Err(anyhow!("not supported"));
             }
             _ => {
@@ -245,13 +239,11 @@ fn build_query<'args>(
     let mut bound = query.separated(", ");
     use substreams_sink::pb::value::Typed;
     for v in args {
-        let typed = v
-            .new_value
-            .as_ref()
-            .ok_or(anyhow!("Failed to get typed value"))?
-            .typed
-            .to_owned()
-            .ok_or(anyhow!("Failed to access typed value"))?;
+        // absent values are NULL
+        let typed = match v.new_value.as_ref().and_then(|v| v.typed.to_owned()) {
+            Some(typed) => typed,
+            None => Typed::Null(pb::Null {}),
+        };
         match typed {
             Typed::Int32(v) => {
                 bound.push_bind(v);
@@ -265,8 +257,10 @@ fn build_query<'args>(
             Typed::Uint64(v) => {
                 bound.push_bind(i64::try_from(v)?);
             }
-            Typed::Bigdecimal(v) => {
-                bound.push_bind(v.to_owned());
+            Typed::Bigdecimal(v) | Typed::Bigint(v) => {
+                // bound as text, to keep their precision
+                bound.push_bind(v);
+                bound.push_unseparated("::numeric");
             }
             Typed::Bytes(v) => {
                 bound.push_bind(v);
@@ -277,13 +271,125 @@ fn build_query<'args>(
             Typed::String(v) => {
                 bound.push_bind(v);
             }
-            _ => unreachable!("filtered in the previous step"),
+            Typed::Json(v) => {
+                // bound as text, the document is validated by postgres
+                bound.push_bind(v);
+                bound.push_unseparated("::jsonb");
+            }
+            Typed::Array(v) => {
+                push_array_bind(&mut bound, v)?;
+            }
+            Typed::Null(_) => {
+                // left untyped, so that it takes the type of its column
+                bound.push("NULL");
+            }
+            Typed::Offchaindata(_) => unreachable!("filtered in the previous step"),
         };
     }
     bound.push_unseparated(")");
     Ok(query)
 }
 
+/// Binds a one-dimensional array, whose elements are of a same type. Empty arrays
+/// are not bound, but left untyped, so that they take the type of their column.
+fn push_array_bind<'qb, 'args: 'qb>(
+    bound: &mut Separated<'qb, 'args, Postgres, &'static str>,
+    array: pb::Array,
+) -> Result<()> {
+    use substreams_sink::pb::value::Typed;
+    let typed = array
+        .value
+        .into_iter()
+        .map(|v| v.typed.ok_or(anyhow!("Failed to access typed value")))
+        .collect::<Result<Vec<_>>>()?;
+    let first = match typed.first() {
+        Some(first) => first.clone(),
+        None => {
+            bound.push("'{}'");
+            return Ok(());
+        }
+    };
+    match first {
+        Typed::Int32(_) => {
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::Int32(v) => Some(v),
+                _ => None,
+            })?);
+        }
+        Typed::Uint32(_) => {
+            bound.push_bind(
+                array_elements(typed, |t| match t {
+                    Typed::Uint32(v) => Some(v),
+                    _ => None,
+                })?
+                .into_iter()
+                .map(i32::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+        Typed::Int64(_) => {
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::Int64(v) => Some(v),
+                _ => None,
+            })?);
+        }
+        Typed::Uint64(_) => {
+            bound.push_bind(
+                array_elements(typed, |t| match t {
+                    Typed::Uint64(v) => Some(v),
+                    _ => None,
+                })?
+                .into_iter()
+                .map(i64::try_from)
+                .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+        Typed::Bigdecimal(_) | Typed::Bigint(_) => {
+            // bound as text, to keep their precision
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::Bigdecimal(v) | Typed::Bigint(v) => Some(v),
+                _ => None,
+            })?);
+            bound.push_unseparated("::numeric[]");
+        }
+        Typed::Bytes(_) => {
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::Bytes(v) => Some(v),
+                _ => None,
+            })?);
+        }
+        Typed::Bool(_) => {
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::Bool(v) => Some(v),
+                _ => None,
+            })?);
+        }
+        Typed::String(_) => {
+            bound.push_bind(array_elements(typed, |t| match t {
+                Typed::String(v) => Some(v),
+                _ => None,
+            })?);
+        }
+        Typed::Array(_) => return Err(anyhow!("multi-dimensional arrays are not supported")),
+        Typed::Offchaindata(_) | Typed::Json(_) | Typed::Null(_) => {
+            return Err(anyhow!("not supported"))
+        }
+    };
+    Ok(())
+}
+
+/// Collects the elements of an array, which are all expected to be of the kind
+/// extracted by `element`.
+fn array_elements<T>(
+    typed: Vec<pb::value::Typed>,
+    element: impl Fn(pb::value::Typed) -> Option<T>,
+) -> Result<Vec<T>> {
+    typed
+        .into_iter()
+        .map(|t| element(t).ok_or(anyhow!("array elements must be of a same type")))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,4 +422,118 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_build_query_with_arrays() -> anyhow::Result<()> {
+        let array = |values: Vec<Typed>| Value {
+            typed: Some(Typed::Array(pb::Array {
+                value: values
+                    .into_iter()
+                    .map(|v| Value { typed: Some(v) })
+                    .collect(),
+            })),
+        };
+        let field = |name: &str, new_value: Value| pb::Field {
+            name: name.to_string(),
+            new_value: Some(new_value),
+            old_value: None,
+        };
+        let test = pb::OffchainDataRecord {
+            record: "table".to_string(),
+            fields: vec![
+                field(
+                    "tags",
+                    array(vec![
+                        Typed::String("a".to_string()),
+                        Typed::String("b".to_string()),
+                    ]),
+                ),
+                field("amounts", array(vec![Typed::Bigint("1".to_string())])),
+                field("topics", array(vec![])),
+                field(
+                    "total",
+                    Value {
+                        typed: Some(Typed::Bigint(u128::MAX.to_string())),
+                    },
+                ),
+            ],
+        };
+        assert_eq!(
+            build_query("manifest", &test)?.into_sql(),
+            "INSERT INTO manifest.table (tags, amounts, topics, total) VALUES ($1, $2::numeric[], '{}', $3::numeric)"
+        );
+
+        let test = pb::OffchainDataRecord {
+            record: "table".to_string(),
+            fields: vec![field(
+                "tags",
+                array(vec![Typed::String("a".to_string()), Typed::Int32(1)]),
+            )],
+        };
+        assert!(build_query("manifest", &test).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_query_with_json() -> anyhow::Result<()> {
+        let test = pb::OffchainDataRecord {
+            record: "table".to_string(),
+            fields: vec![
+                pb::Field {
+                    name: "name".to_string(),
+                    new_value: Some(Value {
+                        typed: Some(Typed::String("test".to_string())),
+                    }),
+                    old_value: None,
+                },
+                pb::Field {
+                    name: "attributes".to_string(),
+                    new_value: Some(Value {
+                        typed: Some(Typed::Json(
+                            r#"[{"traitType": "type", "value": "post"}]"#.to_string(),
+                        )),
+                    }),
+                    old_value: None,
+                },
+            ],
+        };
+        assert_eq!(
+            build_query("manifest", &test)?.into_sql(),
+            "INSERT INTO manifest.table (name, attributes) VALUES ($1, $2::jsonb)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_query_with_nulls() -> anyhow::Result<()> {
+        let test = pb::OffchainDataRecord {
+            record: "table".to_string(),
+            fields: vec![
+                pb::Field {
+                    name: "image".to_string(),
+                    new_value: None,
+                    old_value: None,
+                },
+                pb::Field {
+                    name: "name".to_string(),
+                    new_value: Some(Value {
+                        typed: Some(Typed::String("test".to_string())),
+                    }),
+                    old_value: None,
+                },
+                pb::Field {
+                    name: "content_warning".to_string(),
+                    new_value: Some(Value {
+                        typed: Some(Typed::Null(pb::Null {})),
+                    }),
+                    old_value: None,
+                },
+            ],
+        };
+        assert_eq!(
+            build_query("manifest", &test)?.into_sql(),
+            "INSERT INTO manifest.table (image, name, content_warning) VALUES (NULL, $1, NULL)"
+        );
+        Ok(())
+    }
 }
@@ -1,33 +1,59 @@
 const PROTO_SRC: &'static str = "proto";
-const PROTO_DST: &'static str = "proto/imported";
-const PROTOS: &[&str] = &[
+/// `sf.substreams.v1` protos, from substreams v0.2.0
+const PROTO_V1_DST: &'static str = "proto/imported/v1";
+const PROTOS_V1: &[&str] = &[
     "sf/substreams/v1/substreams.proto",
     "sf/substreams/v1/package.proto",
     "sf/substreams/v1/modules.proto",
     "sf/substreams/v1/clock.proto",
 ];
+/// `sf.substreams.rpc.v2` protos, along with the `sf.substreams.v1` ones they import,
+/// from substreams v1.1.4
+const PROTO_V2_DST: &'static str = "proto/imported/v2";
+const PROTOS_V2: &[&str] = &[
+    "sf/substreams/rpc/v2/service.proto",
+    "sf/substreams/v1/modules.proto",
+    "sf/substreams/v1/clock.proto",
+];
 
-fn main() {
+fn download(tag: &str, protos: &[&str], dst: &str) -> Vec<String> {
     let mut downloader =
-        git_download::repo("https://github.com/streamingfast/substreams").branch_name("v0.2.0");
+        git_download::repo("https://github.com/streamingfast/substreams").branch_name(tag);
 
-    for proto in PROTOS {
+    for proto in protos {
         downloader = downloader.add_file(
             format!("{}/{}", PROTO_SRC, proto),
-            format!("{}/{}", PROTO_DST, proto),
+            format!("{}/{}", dst, proto),
         );
     }
     downloader.exec().unwrap();
 
-    let mut protos = PROTOS
+    protos
         .iter()
-        .map(|proto| format!("{}/{}", PROTO_DST, proto))
-        .collect::<Vec<_>>();
+        .map(|proto| format!("{}/{}", dst, proto))
+        .collect()
+}
+
+fn main() {
+    let mut protos = download("v0.2.0", PROTOS_V1, PROTO_V1_DST);
     protos.push("../proto/eureka/ingest/v1/records.proto".to_string());
 
     tonic_build::configure()
         .protoc_arg("--experimental_allow_proto3_optional")
         .build_client(true)
-        .compile(&protos, &[PROTO_DST, "../proto"])
+        .compile(&protos, &[PROTO_V1_DST, "../proto"])
+        .unwrap();
+
+    // both versions define the `sf.substreams.v1` package, thus the
+    // v2 protos are generated in their own directory
+    let protos = download("v1.1.4", PROTOS_V2, PROTO_V2_DST);
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("v2");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    tonic_build::configure()
+        .protoc_arg("--experimental_allow_proto3_optional")
+        .build_client(true)
+        .out_dir(out_dir)
+        .compile(&protos, &[PROTO_V2_DST])
         .unwrap();
 }
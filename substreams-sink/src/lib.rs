@@ -1,3 +1,6 @@
+#[macro_use]
+extern crate log;
+
 pub mod pb {
     tonic::include_proto!("eureka.ingest.v1");
 }
@@ -5,10 +8,35 @@ pub mod substreams {
     pub mod pb {
         tonic::include_proto!("sf.substreams.v1");
     }
+    /// `sf.substreams.v1` types of the `sf.substreams.rpc.v2` protocol, which come from
+    /// a later substreams release than the ones of [`pb`].
+    pub mod v1 {
+        include!(concat!(env!("OUT_DIR"), "/v2/sf.substreams.v1.rs"));
+    }
+    pub mod rpc {
+        pub mod v2 {
+            include!(concat!(env!("OUT_DIR"), "/v2/sf.substreams.rpc.v2.rs"));
+        }
+    }
 }
+pub mod package;
+pub mod progress;
+pub mod resilient;
+pub mod stream;
 
+pub use package::{ModuleInfo, ModuleInput, ModuleKind, PackageInfo, RECORD_CHANGES_TYPE};
 pub use pb::{OffchainData, OffchainDataContent, OffchainDataRecord, OffchainDataRecords};
-use substreams::pb::{stream_client::StreamClient, Package, PackageMetadata, Request, Response};
+pub use progress::{ModuleStatus, ProgressStatus, ProgressTracker};
+pub use resilient::{
+    is_retryable, BackoffPolicy, ResilientStream, StreamError, DEFAULT_MAX_RETRIES,
+};
+pub use stream::{
+    BlockRange, BlockScopedData, MapOutput, MessageStream, ModuleProgress, ModuleProgressKind,
+    ProtocolVersion, SessionInit, StreamMessage,
+};
+use substreams::pb::{stream_client::StreamClient, ForkStep, Package, PackageMetadata, Request};
+use substreams::rpc::v2;
+use tokio_stream::StreamExt;
 use tonic::{
     codegen::{http::uri::Scheme, http::uri::Uri, *},
     metadata::AsciiMetadataValue,
@@ -59,6 +87,7 @@ impl Cursor {
 }
 
 /// Adds authorization token to request header
+#[derive(Clone)]
 pub struct AuthorizationTokenInjector {
     token: Option<AsciiMetadataValue>,
 }
@@ -76,7 +105,9 @@ impl Interceptor for AuthorizationTokenInjector {
 
 pub struct SubstreamsSink<T> {
     inner: StreamClient<InterceptedService<T, AuthorizationTokenInjector>>,
+    inner_v2: v2::stream_client::StreamClient<InterceptedService<T, AuthorizationTokenInjector>>,
     package: Package,
+    protocol_version: ProtocolVersion,
 }
 
 impl SubstreamsSink<tonic::transport::Channel> {
@@ -103,21 +134,49 @@ impl SubstreamsSink<tonic::transport::Channel> {
             _ => panic!("invalid uri scheme"),
         };
 
+        let injector = AuthorizationTokenInjector { token: api_token };
+
         Ok(SubstreamsSink {
-            inner: StreamClient::with_interceptor(
-                conn,
-                AuthorizationTokenInjector { token: api_token },
-            )
-            .accept_compressed(CompressionEncoding::Gzip)
-            .send_compressed(CompressionEncoding::Gzip),
-            package: ::prost::Message::decode(&std::fs::read(package_file_name)?[..])?,
+            inner: StreamClient::with_interceptor(conn.clone(), injector.clone())
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip),
+            inner_v2: v2::stream_client::StreamClient::with_interceptor(conn, injector)
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip),
+            package: package::read_package(package_file_name)?,
+            protocol_version: ProtocolVersion::default(),
         })
     }
 
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// Sets the protocol version spoken with the endpoint, [`ProtocolVersion::V2`] by default.
+    pub fn set_protocol_version(&mut self, protocol_version: ProtocolVersion) {
+        self.protocol_version = protocol_version;
+    }
+
     pub fn get_package_meta(&self) -> &Vec<PackageMetadata> {
         &self.package.package_meta
     }
 
+    /// Computes the hash of output module `module_name`, which identifies its cursors,
+    /// see [`package::module_hash`].
+    pub fn get_output_module_hash(&self, module_name: &str) -> anyhow::Result<String> {
+        let modules = self
+            .package
+            .modules
+            .as_ref()
+            .ok_or(anyhow::anyhow!("Failed to find modules in package"))?;
+        package::module_hash(modules, module_name)
+    }
+
+    /// Describes the package, along with its modules.
+    pub fn get_package_info(&self) -> anyhow::Result<PackageInfo> {
+        PackageInfo::new(&self.package)
+    }
+
     pub fn get_binary(&self, module_name: &str) -> Option<&[u8]> {
         if let Some(modules) = &self.package.modules {
             modules.modules.iter().find_map(|m| {
@@ -138,15 +197,18 @@ impl SubstreamsSink<tonic::transport::Channel> {
         }
     }
 
-    /// Create stream for a manifest package module.
+    /// Create stream for a manifest package module, with the protocol version of the
+    /// sink. Only final blocks are streamed if `final_blocks_only`, otherwise the chain
+    /// head is followed, and forked blocks are signaled with
+    /// [`StreamMessage::BlockUndoSignal`].
     pub async fn get_stream(
         &mut self,
         module_name: &str,
         start_block_num: i64,
         stop_block_num: u64,
         start_cursor: &str,
-        irreversibility_condition: &str,
-    ) -> Result<tonic::Response<tonic::codec::Streaming<Response>>, tonic::Status> {
+        final_blocks_only: bool,
+    ) -> Result<MessageStream, tonic::Status> {
         let modules = self
             .package
             .modules
@@ -155,18 +217,50 @@ impl SubstreamsSink<tonic::transport::Channel> {
                 "failed to find modules in package".to_string(),
             ))?;
 
-        let request = Request {
-            start_block_num,
-            start_cursor: start_cursor.to_string(),
-            stop_block_num,
-            fork_steps: vec![],
-            irreversibility_condition: irreversibility_condition.to_string(),
-            output_modules: vec![module_name.to_string()],
-            production_mode: true,
-            debug_initial_store_snapshot_for_modules: vec![],
-            modules: Some(modules),
-            output_module: module_name.to_string(),
-        };
-        self.inner.blocks(request).await
+        match self.protocol_version {
+            ProtocolVersion::V1 => {
+                let fork_steps = if final_blocks_only {
+                    vec![ForkStep::StepIrreversible]
+                } else {
+                    vec![ForkStep::StepNew, ForkStep::StepUndo]
+                };
+                let request = Request {
+                    start_block_num,
+                    start_cursor: start_cursor.to_string(),
+                    stop_block_num,
+                    fork_steps: fork_steps.iter().map(|step| *step as i32).collect(),
+                    irreversibility_condition: String::new(),
+                    output_modules: vec![module_name.to_string()],
+                    production_mode: true,
+                    debug_initial_store_snapshot_for_modules: vec![],
+                    modules: Some(modules),
+                    output_module: module_name.to_string(),
+                };
+                let stream = self.inner.blocks(request).await?.into_inner();
+                Ok(Box::pin(stream.map(|response| {
+                    response.and_then(StreamMessage::try_from)
+                })))
+            }
+            ProtocolVersion::V2 => {
+                // modules are wire compatible across both versions of `sf.substreams.v1`
+                let modules =
+                    ::prost::Message::decode(::prost::Message::encode_to_vec(&modules).as_slice())
+                        .map_err(|e| Status::invalid_argument(format!("invalid modules: {}", e)))?;
+                let request = v2::Request {
+                    start_block_num,
+                    start_cursor: start_cursor.to_string(),
+                    stop_block_num,
+                    final_blocks_only,
+                    production_mode: true,
+                    output_module: module_name.to_string(),
+                    modules: Some(modules),
+                    debug_initial_store_snapshot_for_modules: vec![],
+                };
+                let stream = self.inner_v2.blocks(request).await?.into_inner();
+                Ok(Box::pin(stream.map(|response| {
+                    response.and_then(StreamMessage::try_from)
+                })))
+            }
+        }
     }
 }
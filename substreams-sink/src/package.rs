@@ -0,0 +1,493 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{anyhow, Result};
+use sha1::{Digest, Sha1};
+
+use crate::substreams::pb::{module, Module, Modules, Package, PackageMetadata};
+
+/// Output type of the modules whose records are sunk, see [`crate::pb::RecordChanges`].
+pub const RECORD_CHANGES_TYPE: &str = "eureka.ingest.v1.RecordChanges";
+
+/// Reads a package file (*.spkg).
+pub fn read_package(package_file_name: &str) -> Result<Package> {
+    Ok(::prost::Message::decode(
+        &std::fs::read(package_file_name)?[..],
+    )?)
+}
+
+/// Computes the hash of module `module_name`, following the module hashing of
+/// substreams. It covers the initial block, kind, binary and inputs of the module,
+/// as well as the hashes of all its ancestors, so that it changes whenever any
+/// module the output depends on changes.
+pub fn module_hash(modules: &Modules, module_name: &str) -> Result<String> {
+    let mut hashes = HashMap::new();
+    let hash = ModuleHasher {
+        modules,
+        hashes: &mut hashes,
+    }
+    .hash(module_name, &mut vec![])?;
+
+    Ok(hex::encode(hash))
+}
+
+struct ModuleHasher<'a> {
+    modules: &'a Modules,
+    /// Hashes of the modules hashed so far, by name.
+    hashes: &'a mut HashMap<String, Vec<u8>>,
+}
+
+impl<'a> ModuleHasher<'a> {
+    /// Hashes module `module_name`, where `path` holds the modules depending on it,
+    /// to detect cycles.
+    fn hash(&mut self, module_name: &str, path: &mut Vec<String>) -> Result<Vec<u8>> {
+        if let Some(hash) = self.hashes.get(module_name) {
+            return Ok(hash.clone());
+        }
+        if path.iter().any(|m| m == module_name) {
+            return Err(anyhow!(
+                "Cycle in the modules graph at module {}",
+                module_name
+            ));
+        }
+        let module = self.module(module_name)?;
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"initial_block");
+        buf.extend_from_slice(module.initial_block.to_string().as_bytes());
+        buf.extend_from_slice(b"kind");
+        match module.kind {
+            Some(module::Kind::KindMap(_)) => buf.extend_from_slice(b"map"),
+            Some(module::Kind::KindStore(_)) => buf.extend_from_slice(b"store"),
+            None => return Err(anyhow!("Module {} has no kind", module_name)),
+        }
+        buf.extend_from_slice(b"binary");
+        let binary = self
+            .modules
+            .binaries
+            .get(module.binary_index as usize)
+            .ok_or(anyhow!("Binary of module {} not found", module_name))?;
+        buf.extend_from_slice(binary.r#type.as_bytes());
+        buf.extend_from_slice(&binary.content);
+        buf.extend_from_slice(module.binary_entrypoint.as_bytes());
+
+        buf.extend_from_slice(b"inputs");
+        let mut parents = Vec::new();
+        for input in module.inputs.iter() {
+            match &input.input {
+                Some(module::input::Input::Source(source)) => {
+                    buf.extend_from_slice(b"source");
+                    buf.extend_from_slice(source.r#type.as_bytes());
+                }
+                Some(module::input::Input::Map(map)) => {
+                    buf.extend_from_slice(b"map");
+                    buf.extend_from_slice(map.module_name.as_bytes());
+                    parents.push(map.module_name.clone());
+                }
+                Some(module::input::Input::Store(store)) => {
+                    buf.extend_from_slice(b"store");
+                    buf.extend_from_slice(store.module_name.as_bytes());
+                    buf.extend_from_slice(store.mode.to_string().as_bytes());
+                    parents.push(store.module_name.clone());
+                }
+                None => return Err(anyhow!("Module {} has an empty input", module_name)),
+            }
+        }
+
+        // ancestors are hashed in name order, whatever the order of the inputs
+        path.push(module_name.to_string());
+        let mut ancestors = self.ancestors(&parents)?;
+        ancestors.sort();
+        buf.extend_from_slice(b"ancestors");
+        for ancestor in ancestors.iter() {
+            let hash = self.hash(ancestor, path)?;
+            buf.extend_from_slice(&hash);
+        }
+        path.pop();
+
+        buf.extend_from_slice(b"entrypoint");
+        buf.extend_from_slice(module.name.as_bytes());
+
+        let hash = Sha1::digest(&buf).to_vec();
+        self.hashes.insert(module_name.to_string(), hash.clone());
+
+        Ok(hash)
+    }
+
+    fn module(&self, module_name: &str) -> Result<&'a Module> {
+        self.modules
+            .modules
+            .iter()
+            .find(|m| m.name == module_name)
+            .ok_or(anyhow!("Module {} not found in package", module_name))
+    }
+
+    /// Gets the names of the `parents` modules, along with all their ancestors.
+    fn ancestors(&self, parents: &[String]) -> Result<Vec<String>> {
+        let mut ancestors: Vec<String> = Vec::new();
+        let mut pending = parents.to_vec();
+        while let Some(module_name) = pending.pop() {
+            if ancestors.contains(&module_name) {
+                continue;
+            }
+            for input in self.module(&module_name)?.inputs.iter() {
+                match &input.input {
+                    Some(module::input::Input::Map(map)) => pending.push(map.module_name.clone()),
+                    Some(module::input::Input::Store(store)) => {
+                        pending.push(store.module_name.clone())
+                    }
+                    _ => {}
+                }
+            }
+            ancestors.push(module_name);
+        }
+
+        Ok(ancestors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleKind {
+    Map {
+        /// Output type, e.g. `proto:eureka.ingest.v1.RecordChanges`.
+        output_type: String,
+    },
+    Store {
+        /// Update policy name, e.g. `UPDATE_POLICY_SET`.
+        update_policy: String,
+        value_type: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleInput {
+    /// Blocks, or other data of the chain, of type `r#type`.
+    Source {
+        r#type: String,
+    },
+    Map {
+        module_name: String,
+    },
+    Store {
+        module_name: String,
+        /// Access mode name, e.g. `MODE_GET`.
+        mode: String,
+    },
+}
+
+impl ModuleInput {
+    /// Name of the module of the input, unless it is a source.
+    pub fn module_name(&self) -> Option<&str> {
+        match self {
+            ModuleInput::Source { .. } => None,
+            ModuleInput::Map { module_name } | ModuleInput::Store { module_name, .. } => {
+                Some(module_name)
+            }
+        }
+    }
+}
+
+/// Description of a module of a package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub kind: ModuleKind,
+    pub inputs: Vec<ModuleInput>,
+    pub initial_block: u64,
+    pub binary_index: u32,
+    /// Binary type, e.g. `wasm/rust-v1`.
+    pub binary_type: String,
+    pub binary_entrypoint: String,
+    pub binary_size: usize,
+    /// Hash of the module, see [`module_hash`].
+    pub hash: String,
+    pub doc: Option<String>,
+}
+
+/// Description of a package, along with its modules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageInfo {
+    pub version: u64,
+    /// Metadata of the package, followed by the metadata of its imported packages.
+    pub meta: Vec<PackageMetadata>,
+    /// Modules, in package order.
+    pub modules: Vec<ModuleInfo>,
+    /// Names of the proto files of the package.
+    pub proto_files: Vec<String>,
+}
+
+impl PackageInfo {
+    pub fn new(package: &Package) -> Result<Self> {
+        let modules = package
+            .modules
+            .as_ref()
+            .ok_or(anyhow!("Failed to find modules in package"))?;
+
+        let module_infos = modules
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(i, module)| {
+                let kind = match module.kind.as_ref() {
+                    Some(module::Kind::KindMap(map)) => ModuleKind::Map {
+                        output_type: map.output_type.clone(),
+                    },
+                    Some(module::Kind::KindStore(store)) => ModuleKind::Store {
+                        update_policy: module::kind_store::UpdatePolicy::from_i32(
+                            store.update_policy,
+                        )
+                        .map_or("UNKNOWN".to_string(), |p| p.as_str_name().to_string()),
+                        value_type: store.value_type.clone(),
+                    },
+                    None => return Err(anyhow!("Module {} has no kind", module.name)),
+                };
+                let inputs = module
+                    .inputs
+                    .iter()
+                    .map(|input| match input.input.as_ref() {
+                        Some(module::input::Input::Source(source)) => Ok(ModuleInput::Source {
+                            r#type: source.r#type.clone(),
+                        }),
+                        Some(module::input::Input::Map(map)) => Ok(ModuleInput::Map {
+                            module_name: map.module_name.clone(),
+                        }),
+                        Some(module::input::Input::Store(store)) => Ok(ModuleInput::Store {
+                            module_name: store.module_name.clone(),
+                            mode: module::input::store::Mode::from_i32(store.mode)
+                                .map_or("UNKNOWN".to_string(), |m| m.as_str_name().to_string()),
+                        }),
+                        None => Err(anyhow!("Module {} has an empty input", module.name)),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let binary = modules
+                    .binaries
+                    .get(module.binary_index as usize)
+                    .ok_or(anyhow!("Binary of module {} not found", module.name))?;
+
+                Ok(ModuleInfo {
+                    name: module.name.clone(),
+                    kind,
+                    inputs,
+                    initial_block: module.initial_block,
+                    binary_index: module.binary_index,
+                    binary_type: binary.r#type.clone(),
+                    binary_entrypoint: module.binary_entrypoint.clone(),
+                    binary_size: binary.content.len(),
+                    hash: module_hash(modules, &module.name)?,
+                    doc: package
+                        .module_meta
+                        .get(i)
+                        .map(|meta| meta.doc.clone())
+                        .filter(|doc| !doc.is_empty()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            version: package.version,
+            meta: package.package_meta.clone(),
+            modules: module_infos,
+            proto_files: package
+                .proto_files
+                .iter()
+                .map(|file| file.name().to_string())
+                .collect(),
+        })
+    }
+
+    /// Gets a module by name.
+    pub fn module(&self, module_name: &str) -> Option<&ModuleInfo> {
+        self.modules.iter().find(|m| m.name == module_name)
+    }
+
+    /// Gets the modules in dependency order, each one after all the modules it depends
+    /// on. Independent modules are ordered by name.
+    pub fn sorted_modules(&self) -> Result<Vec<&ModuleInfo>> {
+        let mut sorted: Vec<&ModuleInfo> = Vec::with_capacity(self.modules.len());
+        let mut pending = self.modules.iter().collect::<Vec<_>>();
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .filter(|m| {
+                    m.inputs
+                        .iter()
+                        .filter_map(|input| input.module_name())
+                        .all(|name| sorted.iter().any(|s| s.name == name))
+                })
+                .map(|m| m.name.clone())
+                .collect::<BTreeSet<_>>();
+            if ready.is_empty() {
+                return Err(anyhow!(
+                    "Modules {} depend on missing modules, or on each other",
+                    pending
+                        .iter()
+                        .map(|m| m.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            for name in ready.iter() {
+                sorted.push(self.module(name).expect("module of package"));
+            }
+            pending.retain(|m| !ready.contains(&m.name));
+        }
+
+        Ok(sorted)
+    }
+
+    /// Checks that module `module_name` is a map module with output type `output_type`,
+    /// e.g. [`RECORD_CHANGES_TYPE`].
+    pub fn validate_output_module(&self, module_name: &str, output_type: &str) -> Result<()> {
+        let module = self
+            .module(module_name)
+            .ok_or(anyhow!("Module {} not found in package", module_name))?;
+        match &module.kind {
+            ModuleKind::Map {
+                output_type: module_output_type,
+            } => {
+                // output types are declared as `proto:<message type>`
+                let module_output_type = module_output_type
+                    .strip_prefix("proto:")
+                    .unwrap_or(module_output_type);
+                if module_output_type != output_type {
+                    return Err(anyhow!(
+                        "Module {} outputs {}, instead of {}",
+                        module_name,
+                        module_output_type,
+                        output_type
+                    ));
+                }
+                Ok(())
+            }
+            ModuleKind::Store { .. } => Err(anyhow!(
+                "Module {} is a store module, instead of a map module",
+                module_name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::substreams::pb::Binary;
+
+    fn map_module(name: &str, inputs: Vec<module::Input>) -> Module {
+        Module {
+            name: name.to_string(),
+            kind: Some(module::Kind::KindMap(module::KindMap {
+                output_type: "proto:eureka.ingest.v1.RecordChanges".to_string(),
+            })),
+            binary_index: 0,
+            binary_entrypoint: name.to_string(),
+            inputs,
+            output: None,
+            initial_block: 100,
+        }
+    }
+
+    fn map_input(module_name: &str) -> module::Input {
+        module::Input {
+            input: Some(module::input::Input::Map(module::input::Map {
+                module_name: module_name.to_string(),
+            })),
+        }
+    }
+
+    fn modules() -> Modules {
+        let source = module::Input {
+            input: Some(module::input::Input::Source(module::input::Source {
+                r#type: "sf.ethereum.type.v2.Block".to_string(),
+            })),
+        };
+        Modules {
+            modules: vec![
+                map_module("map_blocks", vec![source.clone()]),
+                map_module("map_posts", vec![source]),
+                map_module(
+                    "map_records",
+                    vec![map_input("map_blocks"), map_input("map_posts")],
+                ),
+            ],
+            binaries: vec![Binary {
+                r#type: "wasm/rust-v1".to_string(),
+                content: vec![0, 97, 115, 109],
+            }],
+        }
+    }
+
+    #[test]
+    fn it_works_package_info() {
+        let mut modules = modules();
+        // dependency order differs from package order
+        modules.modules.rotate_left(2);
+        modules.modules[1].kind = Some(module::Kind::KindMap(module::KindMap {
+            output_type: "proto:eureka.ingest.v1.Blocks".to_string(),
+        }));
+        let package = Package {
+            modules: Some(modules),
+            ..Default::default()
+        };
+
+        let info = PackageInfo::new(&package).unwrap();
+        assert_eq!(
+            info.modules
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<_>>(),
+            ["map_records", "map_blocks", "map_posts"]
+        );
+        assert_eq!(
+            info.sorted_modules()
+                .unwrap()
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect::<Vec<_>>(),
+            ["map_blocks", "map_posts", "map_records"]
+        );
+        let module = info.module("map_records").unwrap();
+        assert_eq!(module.binary_size, 4);
+        assert_eq!(module.inputs[0].module_name(), Some("map_blocks"));
+
+        assert!(info
+            .validate_output_module("map_records", RECORD_CHANGES_TYPE)
+            .is_ok());
+        assert!(info
+            .validate_output_module("map_blocks", RECORD_CHANGES_TYPE)
+            .is_err());
+        assert!(info
+            .validate_output_module("map_unknown", RECORD_CHANGES_TYPE)
+            .is_err());
+    }
+
+    #[test]
+    fn it_works_module_hash() {
+        let modules = modules();
+        let hash = module_hash(&modules, "map_records").unwrap();
+        assert_eq!(hash.len(), 40);
+        assert_eq!(module_hash(&modules, "map_records").unwrap(), hash);
+        assert_ne!(module_hash(&modules, "map_posts").unwrap(), hash);
+
+        // inputs are hashed in order, ancestors are not
+        let mut reordered = modules.clone();
+        reordered.modules[2].inputs.reverse();
+        assert_ne!(module_hash(&reordered, "map_records").unwrap(), hash);
+
+        // changing an ancestor changes the hash
+        let mut changed = modules.clone();
+        changed.modules[0].initial_block = 200;
+        assert_ne!(module_hash(&changed, "map_records").unwrap(), hash);
+        assert_eq!(
+            module_hash(&changed, "map_posts").unwrap(),
+            module_hash(&modules, "map_posts").unwrap()
+        );
+        let mut changed = modules.clone();
+        changed.binaries[0].content.push(0);
+        assert_ne!(module_hash(&changed, "map_records").unwrap(), hash);
+
+        assert!(module_hash(&modules, "map_unknown").is_err());
+        let mut cyclic = modules;
+        cyclic.modules[0].inputs.push(map_input("map_records"));
+        assert!(module_hash(&cyclic, "map_records").is_err());
+    }
+}
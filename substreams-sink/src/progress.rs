@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{BlockRange, ModuleProgressKind, StreamMessage};
+
+/// Progress of a module, accumulated from the progress messages of a stream.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ModuleStatus {
+    /// Ranges of blocks processed so far, sorted and merged.
+    pub processed_ranges: Vec<BlockRange>,
+    pub total_bytes_read: u64,
+    pub total_bytes_written: u64,
+    /// Reason of the failure of the module, if any.
+    pub failure: Option<String>,
+}
+
+impl ModuleStatus {
+    /// Adds processed `ranges`, merging the overlapping and contiguous ones.
+    fn add_ranges(&mut self, ranges: &[BlockRange]) {
+        self.processed_ranges.extend_from_slice(ranges);
+        self.processed_ranges.sort_by_key(|r| r.start_block);
+
+        let mut merged: Vec<BlockRange> = Vec::with_capacity(self.processed_ranges.len());
+        for range in self.processed_ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start_block <= last.end_block => {
+                    last.end_block = last.end_block.max(range.end_block);
+                }
+                _ => merged.push(range),
+            }
+        }
+        self.processed_ranges = merged;
+    }
+
+    /// End of the processed blocks starting from `start_block`, if processed.
+    fn processed_up_to(&self, start_block: u64) -> Option<u64> {
+        self.processed_ranges
+            .iter()
+            .find(|r| r.start_block <= start_block && start_block < r.end_block)
+            .map(|r| r.end_block)
+    }
+}
+
+/// Snapshot of the progress of a stream, see [`ProgressTracker::status`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProgressStatus {
+    pub start_block: u64,
+    /// Block at which the stream stops, 0 if it does not.
+    pub end_block: u64,
+    /// Blocks before this one are processed, either in parallel or streamed.
+    pub current_block: u64,
+    /// Last streamed block.
+    pub last_streamed_block: Option<u64>,
+    pub elapsed_secs: u64,
+    /// Blocks processed per second, on average since streaming started.
+    pub blocks_per_sec: f64,
+    /// Estimated time to reach the end block, if known.
+    pub eta_secs: Option<u64>,
+    pub modules: BTreeMap<String, ModuleStatus>,
+}
+
+/// Tracks the progress of a stream towards its end block, from its messages.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    start_block: u64,
+    end_block: u64,
+    started_at: Instant,
+    last_streamed_block: Option<u64>,
+    modules: BTreeMap<String, ModuleStatus>,
+}
+
+impl ProgressTracker {
+    /// Creates a tracker of a stream from `start_block` to `end_block`, 0 if the
+    /// stream does not stop.
+    pub fn new(start_block: u64, end_block: u64) -> Self {
+        Self {
+            start_block,
+            end_block,
+            started_at: Instant::now(),
+            last_streamed_block: None,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Updates the progress with a message of the stream.
+    pub fn on_message(&mut self, message: &StreamMessage) {
+        match message {
+            StreamMessage::SessionInit(session) => {
+                // the start block of a resumed stream is resolved from its cursor
+                self.start_block = session.resolved_start_block;
+                self.started_at = Instant::now();
+            }
+            StreamMessage::BlockScopedData(data) => {
+                self.last_streamed_block = Some(data.cursor.block.num);
+            }
+            StreamMessage::Progress(modules) => {
+                for module in modules {
+                    let status = self.modules.entry(module.name.clone()).or_default();
+                    match &module.kind {
+                        ModuleProgressKind::ProcessedRanges(ranges) => status.add_ranges(ranges),
+                        ModuleProgressKind::ProcessedBytes {
+                            total_bytes_read,
+                            total_bytes_written,
+                        } => {
+                            status.total_bytes_read = *total_bytes_read;
+                            status.total_bytes_written = *total_bytes_written;
+                        }
+                        ModuleProgressKind::Failed { reason, .. } => {
+                            status.failure = Some(reason.clone());
+                        }
+                        ModuleProgressKind::InitialState { .. } => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Gets the current progress.
+    pub fn status(&self) -> ProgressStatus {
+        self.status_at(self.started_at.elapsed())
+    }
+
+    fn status_at(&self, elapsed: Duration) -> ProgressStatus {
+        // blocks processed in parallel count as long as they follow the start block
+        let current_block = self
+            .modules
+            .values()
+            .filter_map(|m| m.processed_up_to(self.start_block))
+            .chain(self.last_streamed_block.map(|b| b + 1))
+            .max()
+            .unwrap_or(self.start_block)
+            .max(self.start_block);
+
+        let blocks = current_block - self.start_block;
+        let blocks_per_sec = if elapsed.is_zero() {
+            0.0
+        } else {
+            blocks as f64 / elapsed.as_secs_f64()
+        };
+        let eta_secs = if self.end_block > 0 && blocks_per_sec > 0.0 {
+            let remaining_blocks = self.end_block.saturating_sub(current_block);
+            Some((remaining_blocks as f64 / blocks_per_sec).ceil() as u64)
+        } else {
+            None
+        };
+
+        ProgressStatus {
+            start_block: self.start_block,
+            end_block: self.end_block,
+            current_block,
+            last_streamed_block: self.last_streamed_block,
+            elapsed_secs: elapsed.as_secs(),
+            blocks_per_sec,
+            eta_secs,
+            modules: self.modules.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockRef, BlockScopedData, Cursor, ModuleProgress};
+
+    fn range(start_block: u64, end_block: u64) -> BlockRange {
+        BlockRange {
+            start_block,
+            end_block,
+        }
+    }
+
+    fn processed_ranges(name: &str, ranges: Vec<BlockRange>) -> StreamMessage {
+        StreamMessage::Progress(vec![ModuleProgress {
+            name: name.to_string(),
+            kind: ModuleProgressKind::ProcessedRanges(ranges),
+        }])
+    }
+
+    #[test]
+    fn it_works_add_ranges() {
+        let mut status = ModuleStatus::default();
+        status.add_ranges(&[range(200, 300), range(0, 100)]);
+        status.add_ranges(&[range(100, 150), range(250, 400), range(500, 600)]);
+        assert_eq!(
+            status.processed_ranges,
+            vec![range(0, 150), range(200, 400), range(500, 600)]
+        );
+        assert_eq!(status.processed_up_to(120), Some(150));
+        assert_eq!(status.processed_up_to(150), None);
+    }
+
+    #[test]
+    fn it_works_status() {
+        let mut tracker = ProgressTracker::new(1000, 3000);
+        let status = tracker.status_at(Duration::ZERO);
+        assert_eq!(status.current_block, 1000);
+        assert_eq!(status.blocks_per_sec, 0.0);
+        assert_eq!(status.eta_secs, None);
+
+        tracker.on_message(&processed_ranges("store_posts", vec![range(0, 1200)]));
+        tracker.on_message(&processed_ranges("map_posts", vec![range(1000, 1100)]));
+        let status = tracker.status_at(Duration::from_secs(10));
+        assert_eq!(status.current_block, 1200);
+        assert_eq!(status.blocks_per_sec, 20.0);
+        assert_eq!(status.eta_secs, Some(90));
+        assert_eq!(
+            status.modules["map_posts"].processed_ranges,
+            [range(1000, 1100)]
+        );
+
+        tracker.on_message(&StreamMessage::BlockScopedData(BlockScopedData {
+            cursor: Cursor::new("cursor".to_string(), BlockRef::new("id".to_string(), 1999)),
+            timestamp: None,
+            outputs: vec![],
+            final_block_height: None,
+        }));
+        let status = tracker.status_at(Duration::from_secs(20));
+        assert_eq!(status.current_block, 2000);
+        assert_eq!(status.last_streamed_block, Some(1999));
+        assert_eq!(status.blocks_per_sec, 50.0);
+        assert_eq!(status.eta_secs, Some(20));
+    }
+}
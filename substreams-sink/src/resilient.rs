@@ -0,0 +1,314 @@
+use std::{fmt, time::Duration};
+
+use tokio_stream::StreamExt;
+use tonic::{transport::Channel, Code, Status};
+
+use crate::{MessageStream, ModuleProgress, ModuleProgressKind, StreamMessage, SubstreamsSink};
+
+/// Default maximum number of consecutive reconnections of a [`ResilientStream`], which
+/// spans about 5 minutes with the default delays.
+pub const DEFAULT_MAX_RETRIES: u32 = 10;
+
+/// Exponential backoff between the reconnections of a [`ResilientStream`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffPolicy {
+    /// Delay before the first reconnection.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay, however many reconnections failed.
+    pub max_delay: Duration,
+    /// Factor by which the delay grows after each failed reconnection.
+    pub multiplier: u32,
+    /// Maximum number of consecutive reconnections, unlimited if `None`. As a module
+    /// failing deterministically may be reported with a retryable error, reconnections
+    /// should be bounded, so that such failures end the stream.
+    pub max_retries: Option<u32>,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(45),
+            multiplier: 2,
+            max_retries: Some(DEFAULT_MAX_RETRIES),
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Delay before the reconnection following `attempt` consecutive failed ones.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.multiplier
+            .checked_pow(attempt)
+            .and_then(|factor| self.initial_delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Whether a stream failing with `status` may succeed once reconnected. Errors of the
+/// request itself, such as an invalid argument or a failed authentication, are fatal.
+pub fn is_retryable(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unavailable
+            | Code::Unknown
+            | Code::Internal
+            | Code::Cancelled
+            | Code::Aborted
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+    )
+}
+
+/// Gets the failure of the first failed module among `modules`, if any.
+fn module_failure(modules: &[ModuleProgress]) -> Option<StreamError> {
+    modules.iter().find_map(|module| match &module.kind {
+        ModuleProgressKind::Failed { reason, logs, .. } => Some(StreamError::ModuleFailed {
+            module_name: module.name.clone(),
+            reason: reason.clone(),
+            logs: logs.clone(),
+        }),
+        _ => None,
+    })
+}
+
+/// Error of a [`ResilientStream`], after which it ends.
+#[derive(Debug)]
+pub enum StreamError {
+    /// The stream failed with an error which is not retryable, see [`is_retryable`].
+    Fatal(Status),
+    /// The stream kept failing after the maximum number of reconnections.
+    RetriesExhausted { retries: u32, status: Status },
+    /// A module failed while processing blocks, as reported by a progress message.
+    /// Such failures are deterministic, thus not retried.
+    ModuleFailed {
+        module_name: String,
+        reason: String,
+        logs: Vec<String>,
+    },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Fatal(status) => write!(
+                f,
+                "fatal stream error {:?}: {}",
+                status.code(),
+                status.message()
+            ),
+            StreamError::RetriesExhausted { retries, status } => write!(
+                f,
+                "stream failed after {} reconnections, {:?}: {}",
+                retries,
+                status.code(),
+                status.message()
+            ),
+            StreamError::ModuleFailed {
+                module_name,
+                reason,
+                ..
+            } => write!(f, "module {} failed: {}", module_name, reason),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Stream of a [`SubstreamsSink`] which reconnects on retryable errors, with exponential
+/// backoff, and resumes from the cursor of the last message it returned.
+pub struct ResilientStream<'a> {
+    sink: &'a mut SubstreamsSink<Channel>,
+    module_name: String,
+    start_block_num: i64,
+    stop_block_num: u64,
+    final_blocks_only: bool,
+    backoff: BackoffPolicy,
+    /// Cursor from which streaming resumes.
+    cursor: String,
+    stream: Option<MessageStream>,
+    /// Number of consecutive reconnections, reset once a message is received.
+    retries: u32,
+    done: bool,
+}
+
+impl<'a> ResilientStream<'a> {
+    /// Creates a stream for a manifest package module, see [`SubstreamsSink::get_stream`].
+    /// The stream connects on the first call to [`ResilientStream::next`].
+    pub fn new(
+        sink: &'a mut SubstreamsSink<Channel>,
+        module_name: &str,
+        start_block_num: i64,
+        stop_block_num: u64,
+        start_cursor: &str,
+        final_blocks_only: bool,
+        backoff: BackoffPolicy,
+    ) -> Self {
+        Self {
+            sink,
+            module_name: module_name.to_string(),
+            start_block_num,
+            stop_block_num,
+            final_blocks_only,
+            backoff,
+            cursor: start_cursor.to_string(),
+            stream: None,
+            retries: 0,
+            done: false,
+        }
+    }
+
+    /// Cursor from which streaming resumes on reconnection.
+    pub fn get_cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    /// Gets the next message, reconnecting as long as the stream fails with retryable
+    /// errors. Returns `None` once the stream ends, or after an error.
+    pub async fn next(&mut self) -> Option<Result<StreamMessage, StreamError>> {
+        while !self.done {
+            if self.stream.is_none() {
+                let stream = self
+                    .sink
+                    .get_stream(
+                        &self.module_name,
+                        self.start_block_num,
+                        self.stop_block_num,
+                        &self.cursor,
+                        self.final_blocks_only,
+                    )
+                    .await;
+                match stream {
+                    Ok(stream) => self.stream = Some(stream),
+                    Err(status) => {
+                        if let Err(e) = self.backoff(status).await {
+                            return Some(Err(e));
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let message = match self.stream.as_mut() {
+                Some(stream) => stream.next().await,
+                None => continue,
+            };
+            match message {
+                Some(Ok(message)) => {
+                    self.retries = 0;
+                    match &message {
+                        StreamMessage::BlockScopedData(data) => {
+                            self.cursor = data.cursor.cursor.clone();
+                        }
+                        StreamMessage::BlockUndoSignal { last_valid_cursor } => {
+                            self.cursor = last_valid_cursor.cursor.clone();
+                        }
+                        StreamMessage::Progress(modules) => {
+                            if let Some(e) = module_failure(modules) {
+                                self.done = true;
+                                return Some(Err(e));
+                            }
+                        }
+                        _ => {}
+                    }
+                    return Some(Ok(message));
+                }
+                Some(Err(status)) => {
+                    self.stream = None;
+                    if let Err(e) = self.backoff(status).await {
+                        return Some(Err(e));
+                    }
+                }
+                None => self.done = true,
+            }
+        }
+
+        None
+    }
+
+    /// Waits before reconnecting after the stream failed with `status`, or ends the
+    /// stream if the error is fatal, or if there were too many reconnections.
+    async fn backoff(&mut self, status: Status) -> Result<(), StreamError> {
+        if !is_retryable(&status) {
+            self.done = true;
+            return Err(StreamError::Fatal(status));
+        }
+        if Some(self.retries) == self.backoff.max_retries {
+            self.done = true;
+            return Err(StreamError::RetriesExhausted {
+                retries: self.retries,
+                status,
+            });
+        }
+
+        let delay = self.backoff.delay(self.retries);
+        self.retries += 1;
+        warn!(
+            "Stream failed with {:?}: {}, reconnecting in {:?} (attempt {})",
+            status.code(),
+            status.message(),
+            delay,
+            self.retries
+        );
+        tokio::time::sleep(delay).await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_backoff_delay() {
+        let backoff = BackoffPolicy::default();
+        assert_eq!(backoff.delay(0), Duration::from_millis(500));
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(3), Duration::from_secs(4));
+        assert_eq!(backoff.delay(7), Duration::from_secs(45));
+        // overflowing delays are bounded as well
+        assert_eq!(backoff.delay(64), Duration::from_secs(45));
+        // reconnections are bounded by default
+        assert_eq!(backoff.max_retries, Some(DEFAULT_MAX_RETRIES));
+    }
+
+    #[test]
+    fn it_works_module_failure() {
+        let progress = |name: &str, kind| ModuleProgress {
+            name: name.to_string(),
+            kind,
+        };
+        let mut modules = vec![progress(
+            "map_posts",
+            ModuleProgressKind::ProcessedBytes {
+                total_bytes_read: 10,
+                total_bytes_written: 20,
+            },
+        )];
+        assert!(module_failure(&modules).is_none());
+
+        modules.push(progress(
+            "store_posts",
+            ModuleProgressKind::Failed {
+                reason: "wasm panic".to_string(),
+                logs: vec!["index out of bounds".to_string()],
+                logs_truncated: false,
+            },
+        ));
+        assert!(matches!(
+            module_failure(&modules),
+            Some(StreamError::ModuleFailed { module_name, reason, .. })
+                if module_name == "store_posts" && reason == "wasm panic"
+        ));
+    }
+
+    #[test]
+    fn it_works_is_retryable() {
+        assert!(is_retryable(&Status::unavailable("connection reset")));
+        assert!(is_retryable(&Status::internal("h2 protocol error")));
+        assert!(!is_retryable(&Status::invalid_argument("invalid module")));
+        assert!(!is_retryable(&Status::unauthenticated("invalid token")));
+        assert!(!is_retryable(&Status::permission_denied("quota exceeded")));
+    }
+}
@@ -0,0 +1,284 @@
+use std::pin::Pin;
+
+use serde::Serialize;
+use tokio_stream::Stream;
+use tonic::Status;
+
+use crate::{
+    substreams::{pb as v1, rpc::v2},
+    BlockRef, Cursor,
+};
+
+/// Version of the Substreams RPC protocol spoken with the endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    /// `sf.substreams.v1`, where forks are streamed as undo steps.
+    V1,
+    /// `sf.substreams.rpc.v2`, where forks are streamed as block undo signals.
+    #[default]
+    V2,
+}
+
+/// Output of a map module for a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapOutput {
+    pub name: String,
+    pub type_url: String,
+    pub value: Vec<u8>,
+}
+
+/// Outputs of a block, along with the cursor to resume streaming after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockScopedData {
+    pub cursor: Cursor,
+    pub timestamp: Option<prost_types::Timestamp>,
+    pub outputs: Vec<MapOutput>,
+    /// Number of the last final block, only known with [`ProtocolVersion::V2`].
+    pub final_block_height: Option<u64>,
+}
+
+/// Session opened by the endpoint, sent first with [`ProtocolVersion::V2`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInit {
+    pub trace_id: String,
+    /// Block from which streaming actually starts, once the start cursor is resolved.
+    pub resolved_start_block: u64,
+    /// Block from which blocks are processed linearly, instead of in parallel.
+    pub linear_handoff_block: u64,
+}
+
+/// Range of blocks `[start_block, end_block)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BlockRange {
+    pub start_block: u64,
+    pub end_block: u64,
+}
+
+/// Progress of a module, while the endpoint processes blocks in parallel before
+/// streaming them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleProgress {
+    pub name: String,
+    pub kind: ModuleProgressKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleProgressKind {
+    /// Ranges of blocks processed so far.
+    ProcessedRanges(Vec<BlockRange>),
+    /// State of a store module, which is available up to a block.
+    InitialState { available_up_to_block: u64 },
+    ProcessedBytes {
+        total_bytes_read: u64,
+        total_bytes_written: u64,
+    },
+    /// Processing failed, the stream is about to fail as well.
+    Failed {
+        reason: String,
+        logs: Vec<String>,
+        logs_truncated: bool,
+    },
+}
+
+/// Message of a stream, whatever the protocol version.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamMessage {
+    BlockScopedData(BlockScopedData),
+    /// Blocks after the block of `last_valid_cursor` were forked out, and their
+    /// changes must be undone.
+    BlockUndoSignal {
+        last_valid_cursor: Cursor,
+    },
+    SessionInit(SessionInit),
+    Progress(Vec<ModuleProgress>),
+    /// Keys of a store sent so far, when the initial snapshot of a store is requested.
+    SnapshotData {
+        module_name: String,
+        sent_keys: u64,
+        total_keys: u64,
+    },
+    /// Initial store snapshots were all sent, streaming resumes from `cursor`.
+    SnapshotComplete {
+        cursor: String,
+    },
+    /// Messages unknown to this version of the sink, which are ignored.
+    Other,
+}
+
+/// Stream of [`StreamMessage`]s, see [`crate::SubstreamsSink::get_stream`].
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<StreamMessage, Status>> + Send>>;
+
+/// Converts the `ModulesProgress` of either protocol version `$pb`, both of which
+/// define it alike.
+macro_rules! modules_progress {
+    ($pb:ident, $progress:expr) => {
+        $progress
+            .modules
+            .into_iter()
+            .filter_map(|module| {
+                let kind = match module.r#type? {
+                    $pb::module_progress::Type::ProcessedRanges(ranges) => {
+                        ModuleProgressKind::ProcessedRanges(
+                            ranges
+                                .processed_ranges
+                                .into_iter()
+                                .map(|range| BlockRange {
+                                    start_block: range.start_block,
+                                    end_block: range.end_block,
+                                })
+                                .collect(),
+                        )
+                    }
+                    $pb::module_progress::Type::InitialState(state) => {
+                        ModuleProgressKind::InitialState {
+                            available_up_to_block: state.available_up_to_block,
+                        }
+                    }
+                    $pb::module_progress::Type::ProcessedBytes(bytes) => {
+                        ModuleProgressKind::ProcessedBytes {
+                            total_bytes_read: bytes.total_bytes_read,
+                            total_bytes_written: bytes.total_bytes_written,
+                        }
+                    }
+                    $pb::module_progress::Type::Failed(failed) => ModuleProgressKind::Failed {
+                        reason: failed.reason,
+                        logs: failed.logs,
+                        logs_truncated: failed.logs_truncated,
+                    },
+                };
+                Some(ModuleProgress {
+                    name: module.name,
+                    kind,
+                })
+            })
+            .collect()
+    };
+}
+
+impl TryFrom<v1::Response> for StreamMessage {
+    type Error = Status;
+
+    fn try_from(response: v1::Response) -> Result<Self, Status> {
+        let data = match response.message {
+            Some(v1::response::Message::Data(data)) => data,
+            Some(v1::response::Message::Progress(progress)) => {
+                return Ok(StreamMessage::Progress(modules_progress!(v1, progress)));
+            }
+            Some(v1::response::Message::SnapshotData(snapshot)) => {
+                return Ok(StreamMessage::SnapshotData {
+                    module_name: snapshot.module_name,
+                    sent_keys: snapshot.sent_keys,
+                    total_keys: snapshot.total_keys,
+                });
+            }
+            Some(v1::response::Message::SnapshotComplete(complete)) => {
+                return Ok(StreamMessage::SnapshotComplete {
+                    cursor: complete.cursor,
+                });
+            }
+            None => return Ok(StreamMessage::Other),
+        };
+        let clock = data
+            .clock
+            .ok_or(Status::internal("missing clock in block scoped data"))?;
+
+        if data.step == v1::ForkStep::StepUndo as i32 {
+            // the parent of the undone block is the last valid block. Its id
+            // is not part of the undo step, thus it is left blank
+            return Ok(StreamMessage::BlockUndoSignal {
+                last_valid_cursor: Cursor::new(
+                    data.cursor,
+                    BlockRef::new(String::new(), clock.number.saturating_sub(1)),
+                ),
+            });
+        }
+
+        // store deltas are not streamed, as only map modules are requested
+        let outputs = data
+            .outputs
+            .into_iter()
+            .filter_map(|output| match output.data {
+                Some(v1::module_output::Data::MapOutput(any)) => Some(MapOutput {
+                    name: output.name,
+                    type_url: any.type_url,
+                    value: any.value,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(StreamMessage::BlockScopedData(BlockScopedData {
+            cursor: Cursor::new(data.cursor, BlockRef::new(clock.id, clock.number)),
+            timestamp: clock.timestamp,
+            outputs,
+            final_block_height: None,
+        }))
+    }
+}
+
+impl TryFrom<v2::Response> for StreamMessage {
+    type Error = Status;
+
+    fn try_from(response: v2::Response) -> Result<Self, Status> {
+        match response.message {
+            Some(v2::response::Message::BlockScopedData(data)) => {
+                let clock = data
+                    .clock
+                    .ok_or(Status::internal("missing clock in block scoped data"))?;
+                let outputs = data
+                    .output
+                    .into_iter()
+                    .filter_map(|output| {
+                        let any = output.map_output?;
+                        Some(MapOutput {
+                            name: output.name,
+                            type_url: any.type_url,
+                            value: any.value,
+                        })
+                    })
+                    .collect();
+
+                Ok(StreamMessage::BlockScopedData(BlockScopedData {
+                    cursor: Cursor::new(data.cursor, BlockRef::new(clock.id, clock.number)),
+                    timestamp: clock.timestamp,
+                    outputs,
+                    final_block_height: Some(data.final_block_height),
+                }))
+            }
+            Some(v2::response::Message::BlockUndoSignal(signal)) => {
+                let block = signal
+                    .last_valid_block
+                    .ok_or(Status::internal("missing last valid block in undo signal"))?;
+                Ok(StreamMessage::BlockUndoSignal {
+                    last_valid_cursor: Cursor::new(
+                        signal.last_valid_cursor,
+                        BlockRef::new(block.id, block.number),
+                    ),
+                })
+            }
+            Some(v2::response::Message::Session(session)) => {
+                Ok(StreamMessage::SessionInit(SessionInit {
+                    trace_id: session.trace_id,
+                    resolved_start_block: session.resolved_start_block,
+                    linear_handoff_block: session.linear_handoff_block,
+                }))
+            }
+            Some(v2::response::Message::Progress(progress)) => {
+                Ok(StreamMessage::Progress(modules_progress!(v2, progress)))
+            }
+            Some(v2::response::Message::DebugSnapshotData(snapshot)) => {
+                Ok(StreamMessage::SnapshotData {
+                    module_name: snapshot.module_name,
+                    sent_keys: snapshot.sent_keys,
+                    total_keys: snapshot.total_keys,
+                })
+            }
+            Some(v2::response::Message::DebugSnapshotComplete(complete)) => {
+                Ok(StreamMessage::SnapshotComplete {
+                    cursor: complete.cursor,
+                })
+            }
+            _ => Ok(StreamMessage::Other),
+        }
+    }
+}